@@ -0,0 +1,108 @@
+use std::env;
+
+use anyhow::{Error, Result};
+use reqwest::{Client, StatusCode};
+use serenity::async_trait;
+
+use crate::models::lyrics::{LrclibRecord, Lyrics};
+
+/// Somewhere to look lyrics up by title and artist.
+#[async_trait]
+pub trait LyricsProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// `Ok(None)` when the provider has no lyrics for the track.
+    async fn fetch(
+        &self,
+        title: &str,
+        artist: &str,
+        duration_ms: Option<u32>,
+    ) -> Result<Option<Lyrics>, Error>;
+}
+
+/// lrclib.net, free and keyless, has time-synced lyrics for most releases.
+pub struct Lrclib {
+    base: String,
+}
+
+impl Lrclib {
+    /// `LYRICS_API_URL` can point it at a local stub.
+    pub fn from_env() -> Self {
+        Self {
+            base: env::var("LYRICS_API_URL").unwrap_or_else(|_| "https://lrclib.net/api".to_string()),
+        }
+    }
+
+    fn lyrics(record: LrclibRecord) -> Option<Lyrics> {
+        if record.instrumental {
+            return None;
+        }
+
+        match (record.synced_lyrics, record.plain_lyrics) {
+            (Some(synced), _) if !synced.trim().is_empty() => Some(Lyrics::from_lrc(&synced)),
+            (_, Some(plain)) if !plain.trim().is_empty() => Some(Lyrics::plain(&plain)),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for Lrclib {
+    fn name(&self) -> &'static str {
+        "lrclib"
+    }
+
+    async fn fetch(
+        &self,
+        title: &str,
+        artist: &str,
+        duration_ms: Option<u32>,
+    ) -> Result<Option<Lyrics>, Error> {
+        let client = Client::new();
+
+        // The exact lookup needs the duration, search is the fallback when it misses
+        if let Some(ms) = duration_ms {
+            let res = client
+                .get(format!("{}/get", self.base))
+                .query(&[
+                    ("track_name", title),
+                    ("artist_name", artist),
+                    ("duration", &(ms / 1000).to_string()),
+                ])
+                .send()
+                .await?;
+
+            if res.status() != StatusCode::NOT_FOUND {
+                let record = res.error_for_status()?.json::<LrclibRecord>().await?;
+                if let Some(lyrics) = Self::lyrics(record) {
+                    return Ok(Some(lyrics));
+                }
+            }
+        }
+
+        let records = client
+            .get(format!("{}/search", self.base))
+            .query(&[("track_name", title), ("artist_name", artist)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<LrclibRecord>>()
+            .await?;
+
+        Ok(records.into_iter().find_map(Self::lyrics))
+    }
+}
+
+/// Picks the provider from `LYRICS_PROVIDER` (`lrclib` or `none`, default `lrclib`).
+pub fn configured_provider() -> Option<Box<dyn LyricsProvider>> {
+    let provider = env::var("LYRICS_PROVIDER").unwrap_or_else(|_| "lrclib".to_string());
+
+    match provider.to_lowercase().as_str() {
+        "none" | "off" => None,
+        "lrclib" => Some(Box::new(Lrclib::from_env())),
+        other => {
+            tracing::warn!("Unknown LYRICS_PROVIDER {:?}, using lrclib", other);
+            Some(Box::new(Lrclib::from_env()))
+        }
+    }
+}
@@ -1,31 +1,45 @@
-use crate::models::spotify::{SpotifyErrorResponse, SpotifyTrackItem};
+use std::env;
+
+use crate::models::spotify::{
+    SpotifyErrorResponse, SpotifyPlaylistTracksResponse, SpotifyRecommendationsResponse,
+    SpotifyTrackItem,
+};
+use crate::metrics;
 use crate::token::registry::TokenRegistry;
 use anyhow::{Error, Result};
 use reqwest::Client;
 
+/// Base of the Web API, `SPOTIFY_API_URL` can point it at a local stub.
+fn api_base() -> String {
+    env::var("SPOTIFY_API_URL").unwrap_or_else(|_| "https://api.spotify.com/v1".to_string())
+}
+
+/// A valid access token, refreshed when the cached one expired.
+pub async fn get_token(registry: &TokenRegistry) -> Result<String, Error> {
+    let mut guard = registry.spotify.lock().await;
+    match guard.get_token().await {
+        Ok(t) => Ok(t),
+        Err(e) => {
+            tracing::error!("Failed to get token: {:?}", e);
+            Err(anyhow::anyhow!(e.to_string()))
+        }
+    }
+}
+
+#[tracing::instrument(skip(registry))]
 pub async fn get_track_by_id(
     track_id: String,
     registry: TokenRegistry,
 ) -> Result<SpotifyTrackItem, Error> {
-    let token = {
-        let mut guard = registry.spotify.lock().await;
-        match guard.get_token().await {
-            Ok(t) => t,
-            Err(e) => {
-                tracing::error!("Failed to get token: {:?}", e);
-                return Err(anyhow::anyhow!(e.to_string()));
-            }
-        }
-    };
+    let token = get_token(&registry).await?;
 
-    let url = format!("https://api.spotify.com/v1/tracks/{}", track_id);
+    let url = format!("{}/tracks/{}", api_base(), track_id);
 
     let client = Client::new();
-    let res = client
+    let request = client
         .get(&url)
-        .header("Authorization", format!("Bearer {}", token))
-        .send()
-        .await?;
+        .header("Authorization", format!("Bearer {}", token));
+    let res = metrics::api_call("spotify", "tracks", request).await?;
 
     if res.status().is_success() {
         let track = res.json::<SpotifyTrackItem>().await?;
@@ -35,3 +49,71 @@ pub async fn get_track_by_id(
         Err(anyhow::anyhow!("Spotify error {}: {}", err.error.status, err.error.message))
     }
 }
+
+/// Every playable track of a playlist, following pagination.
+#[tracing::instrument(skip(registry))]
+pub async fn get_playlist_tracks(
+    playlist_id: String,
+    registry: TokenRegistry,
+) -> Result<Vec<SpotifyTrackItem>, Error> {
+    let token = get_token(&registry).await?;
+    let client = Client::new();
+
+    let mut tracks = Vec::new();
+    let mut next = Some(format!(
+        "{}/playlists/{}/tracks?limit=100",
+        api_base(),
+        playlist_id
+    ));
+
+    while let Some(url) = next {
+        let request = client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", token));
+        let res = metrics::api_call("spotify", "playlist_tracks", request).await?;
+
+        if !res.status().is_success() {
+            let err = res.json::<SpotifyErrorResponse>().await?;
+            return Err(anyhow::anyhow!("Spotify error {}: {}", err.error.status, err.error.message));
+        }
+
+        let page = res.json::<SpotifyPlaylistTracksResponse>().await?;
+        tracks.extend(
+            page.items
+                .into_iter()
+                .filter_map(|item| item.track)
+                .filter_map(|track| serde_json::from_value::<SpotifyTrackItem>(track).ok()),
+        );
+        next = page.next;
+    }
+
+    Ok(tracks)
+}
+
+/// Tracks Spotify considers similar to the seeds (at most 5 seeds).
+#[tracing::instrument(skip(registry))]
+pub async fn get_recommendations(
+    seed_track_ids: &[String],
+    limit: u32,
+    registry: TokenRegistry,
+) -> Result<Vec<SpotifyTrackItem>, Error> {
+    let token = get_token(&registry).await?;
+
+    let url = format!("{}/recommendations", api_base());
+    let seeds = seed_track_ids.iter().take(5).cloned().collect::<Vec<_>>().join(",");
+
+    let client = Client::new();
+    let request = client
+        .get(&url)
+        .query(&[("seed_tracks", seeds), ("limit", limit.to_string())])
+        .header("Authorization", format!("Bearer {}", token));
+    let res = metrics::api_call("spotify", "recommendations", request).await?;
+
+    if res.status().is_success() {
+        let recommendations = res.json::<SpotifyRecommendationsResponse>().await?;
+        Ok(recommendations.tracks)
+    } else {
+        let err = res.json::<SpotifyErrorResponse>().await?;
+        Err(anyhow::anyhow!("Spotify error {}: {}", err.error.status, err.error.message))
+    }
+}
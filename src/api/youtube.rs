@@ -1,34 +1,255 @@
 use std::env;
 
-use anyhow::Ok;
+use anyhow::{Error, Result};
 use reqwest::Client;
+use serenity::async_trait;
 
-use crate::models::youtube::{ApiYoutubeResponse, YoutubeErrorResponse, YoutubeSearchResult};
+use crate::api::ytdlp;
+use crate::metrics;
+use crate::models::youtube::{ApiYoutubeResponse, YoutubeError, YoutubeErrorResponse, YoutubeSearchResult};
 
-pub async fn search_youtube(query: &str) -> Result<Vec<YoutubeSearchResult>, anyhow::Error> {
-    let client = Client::new();
-    let api_key = env::var("YOUTUBE_API_KEY").expect("Missing Youtube API key, please configure your env");
+/// Base of the Data API, `YOUTUBE_API_URL` can point it at a local stub.
+fn api_base() -> String {
+    env::var("YOUTUBE_API_URL").unwrap_or_else(|_| "https://www.googleapis.com/youtube/v3".to_string())
+}
 
-    let url = "https://www.googleapis.com/youtube/v3/search";
+/// A way of turning a free-text query into YouTube videos.
+#[async_trait]
+pub trait YoutubeSearchBackend: Send + Sync {
+    fn name(&self) -> &'static str;
 
-    let res = client
-        .get(url)
-        .query(&[
+    async fn search(&self, query: &str) -> Result<Vec<YoutubeSearchResult>, Error>;
+}
+
+/// Searches through the YouTube Data API v3, costs quota on every call.
+pub struct DataApiSearch {
+    api_key: String,
+    base: String,
+}
+
+/// Searches through `yt-dlp ytsearch5:`, needs no API key.
+pub struct YtDlpSearch {
+    program: String,
+}
+
+impl DataApiSearch {
+    pub fn new(api_key: String, base: String) -> Self {
+        Self { api_key, base }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let api_key = env::var("YOUTUBE_API_KEY").ok().filter(|k| !k.is_empty())?;
+        Some(Self::new(api_key, api_base()))
+    }
+}
+
+impl YtDlpSearch {
+    /// Searches with the yt-dlp executable at `program`.
+    pub fn new(program: impl Into<String>) -> Self {
+        Self { program: program.into() }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(ytdlp::ytdlp_path())
+    }
+}
+
+#[async_trait]
+impl YoutubeSearchBackend for DataApiSearch {
+    fn name(&self) -> &'static str {
+        "api"
+    }
+
+    #[tracing::instrument(name = "youtube_api_search", skip(self))]
+    async fn search(&self, query: &str) -> Result<Vec<YoutubeSearchResult>, Error> {
+        let client = Client::new();
+        let url = format!("{}/search", self.base);
+
+        let request = client.get(&url).query(&[
             ("part", "snippet"),
             ("maxResults", "5"),
             ("q", query),
             ("type", "video"),
-            ("key", &api_key),
-        ])
-        .send()
-        .await?;
-
-    if res.status().is_success() {
-        let results = res.json::<ApiYoutubeResponse>().await?;
-        let items = results.items.into_iter().map(YoutubeSearchResult::from).collect();
-        Ok(items)
-    } else {
-        let err = res.json::<YoutubeErrorResponse>().await?;
-        Err(anyhow::anyhow!("{} {}", err.error.code, err.error.message))
-    }
-}
\ No newline at end of file
+            ("key", &self.api_key),
+        ]);
+        let res = metrics::api_call("youtube", "search", request).await?;
+
+        if res.status().is_success() {
+            let results = res.json::<ApiYoutubeResponse>().await?;
+            let items = results.items.into_iter().map(YoutubeSearchResult::from).collect();
+            Ok(items)
+        } else {
+            let err = res.json::<YoutubeErrorResponse>().await?;
+            Err(err.error.into())
+        }
+    }
+}
+
+#[async_trait]
+impl YoutubeSearchBackend for YtDlpSearch {
+    fn name(&self) -> &'static str {
+        "ytdlp"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<YoutubeSearchResult>, Error> {
+        let target = format!("ytsearch5:{}", query);
+        let entries =
+            ytdlp::dump_json_with(&self.program, &target, &["--flat-playlist", "--no-warnings"])
+                .await?;
+
+        Ok(entries.into_iter().map(YoutubeSearchResult::from).collect())
+    }
+}
+
+/// Picks the backend from `YOUTUBE_SEARCH_BACKEND` (`api` or `ytdlp`, default `api`).
+/// The Data API is only used when `YOUTUBE_API_KEY` is set.
+fn configured_backend() -> Box<dyn YoutubeSearchBackend> {
+    let backend = env::var("YOUTUBE_SEARCH_BACKEND").unwrap_or_else(|_| "api".to_string());
+
+    match backend.to_lowercase().as_str() {
+        "ytdlp" | "yt-dlp" => Box::new(YtDlpSearch::from_env()),
+        _ => match DataApiSearch::from_env() {
+            Some(api) => Box::new(api),
+            None => {
+                tracing::warn!("YOUTUBE_API_KEY is not set, searching with yt-dlp instead");
+                Box::new(YtDlpSearch::from_env())
+            }
+        },
+    }
+}
+
+#[tracing::instrument]
+pub async fn search_youtube(query: &str) -> Result<Vec<YoutubeSearchResult>, Error> {
+    search_with(configured_backend().as_ref(), &YtDlpSearch::from_env(), query).await
+}
+
+/// Searches with `backend`, turning to `fallback` once the Data API is out of quota.
+async fn search_with(
+    backend: &dyn YoutubeSearchBackend,
+    fallback: &YtDlpSearch,
+    query: &str,
+) -> Result<Vec<YoutubeSearchResult>, Error> {
+    match backend.search(query).await {
+        Err(e) if is_quota_exceeded(&e) && backend.name() != "ytdlp" => {
+            tracing::warn!("YouTube API quota exceeded, falling back to yt-dlp search");
+            fallback.search(query).await
+        }
+        result => result,
+    }
+}
+
+fn is_quota_exceeded(err: &Error) -> bool {
+    err.downcast_ref::<YoutubeError>()
+        .map(|e| e.is_quota_exceeded())
+        .unwrap_or(false)
+}
+
+/// Videos YouTube mixes in with `video_id`, taken from its auto-generated radio playlist.
+#[tracing::instrument]
+pub async fn related_videos(video_id: &str) -> Result<Vec<YoutubeSearchResult>, Error> {
+    let mix = format!("https://www.youtube.com/watch?v={}&list=RD{}", video_id, video_id);
+    let entries = ytdlp::dump_json(
+        &mix,
+        &["--flat-playlist", "--yes-playlist", "--playlist-end", "15", "--no-warnings"],
+    )
+    .await?;
+
+    Ok(entries
+        .into_iter()
+        .map(YoutubeSearchResult::from)
+        .filter(|res| res.video_id != video_id)
+        .collect())
+}
+
+/// The video id of a YouTube watch or short link.
+pub fn video_id(url: &str) -> Option<String> {
+    if let Some(rest) = url.split("youtu.be/").nth(1) {
+        return rest.split(['?', '&', '/']).next().map(str::to_string);
+    }
+
+    url.split(['?', '&'])
+        .find_map(|part| part.strip_prefix("v="))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server, StatusCode};
+
+    use super::*;
+    use crate::sources::youtube::search_result_info;
+
+    const ENTRY: &str = r#"{"id":"dQw4w9WgXcQ","title":"Never Gonna Give You Up","channel":"Rick Astley","uploader":"RickAstleyVEVO","duration":213.0}"#;
+
+    const QUOTA_EXCEEDED: &str = r#"{"error":{"code":403,"message":"Quota exceeded","status":"PERMISSION_DENIED","errors":[{"message":"Quota exceeded","domain":"youtube.quota","reason":"quotaExceeded"}]}}"#;
+
+    /// A stand-in for yt-dlp that prints `ENTRY` for `ytsearch5:` targets and fails otherwise.
+    fn fake_ytdlp(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kumar-ytdlp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let script = dir.join(name);
+        let body = format!(
+            "#!/bin/sh\ncase \"$*\" in\n*ytsearch5:*) cat <<'JSON'\n{}\nJSON\n;;\n*) exit 1 ;;\nesac\n",
+            ENTRY
+        );
+        fs::write(&script, body).unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+
+    /// A Data API stub that answers every request as out of quota, returns its base URL.
+    fn quota_exceeded_api() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_| async {
+                Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .body(Body::from(QUOTA_EXCEEDED))
+            }))
+        });
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_service));
+
+        base
+    }
+
+    fn assert_rick_astley(mut results: Vec<YoutubeSearchResult>) {
+        assert_eq!(results.len(), 1);
+        let track = search_result_info(results.remove(0));
+
+        assert_eq!(track.source, "youtube");
+        assert_eq!(track.url, "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+        assert_eq!(track.title, "Never Gonna Give You Up");
+        assert_eq!(track.artist, "Rick Astley");
+        assert_eq!(track.duration_ms, Some(213_000));
+        assert!(!track.is_live);
+    }
+
+    #[tokio::test]
+    async fn ytdlp_search_parses_its_output() {
+        let search = YtDlpSearch::new(fake_ytdlp("search").to_str().unwrap());
+
+        let results = search.search("never gonna give you up").await.unwrap();
+
+        assert_rick_astley(results);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_ytdlp_once_out_of_quota() {
+        let api = DataApiSearch::new("key".to_string(), quota_exceeded_api());
+        let fallback = YtDlpSearch::new(fake_ytdlp("fallback").to_str().unwrap());
+
+        let results = search_with(&api, &fallback, "never gonna give you up").await.unwrap();
+
+        assert_rick_astley(results);
+    }
+}
@@ -0,0 +1,74 @@
+use std::env;
+use std::process::Stdio;
+use std::sync::OnceLock;
+
+use anyhow::{Error, Result};
+use tokio::process::Command;
+
+use crate::metrics;
+use crate::models::ytdlp::YtDlpEntry;
+
+/// Path of the yt-dlp executable, overridable so a fake script can stand in for it.
+pub fn ytdlp_path() -> &'static str {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(|| env::var("YTDLP_PATH").unwrap_or_else(|_| "yt-dlp".to_string()))
+}
+
+/// Runs yt-dlp with `--dump-json` against `target` and parses one entry per output line.
+pub async fn dump_json(target: &str, args: &[&str]) -> Result<Vec<YtDlpEntry>, Error> {
+    dump_json_with(ytdlp_path(), target, args).await
+}
+
+/// `dump_json` with the yt-dlp executable at `program`.
+#[tracing::instrument(skip(args))]
+pub async fn dump_json_with(
+    program: &str,
+    target: &str,
+    args: &[&str],
+) -> Result<Vec<YtDlpEntry>, Error> {
+    let _timer = metrics::API_LATENCY
+        .with_label_values(&["ytdlp", "dump_json"])
+        .start_timer();
+
+    let output = Command::new(program)
+        .args(args)
+        .arg("--dump-json")
+        .arg("--")
+        .arg(target)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let code = output.status.code().map(|c| c.to_string()).unwrap_or_default();
+        metrics::API_ERRORS.with_label_values(&["ytdlp", &code]).inc();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("yt-dlp exited with {}: {}", output.status, stderr.trim()));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let entries = stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str::<YtDlpEntry>)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(entries)
+}
+
+/// Version of the installed yt-dlp, failing when it cannot be run.
+pub async fn version() -> Result<String, Error> {
+    let output = Command::new(ytdlp_path())
+        .arg("--version")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("yt-dlp exited with {}", output.status));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
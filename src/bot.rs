@@ -1,6 +1,5 @@
 use std::env;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 use reqwest::Client as HttpClient;
 use serenity::model::gateway::GatewayIntents;
@@ -10,16 +9,30 @@ use songbird::SerenityInit;
 
 use crate::commands::music::queue::BotMusicState;
 use crate::handler::Handler;
+use crate::library::Library;
+use crate::server;
+use crate::sources::SourceRegistry;
+use crate::token::registry::TokenRegistry;
 
 pub struct HttpKey;
 pub struct MusicStateKey;
+pub struct SourceRegistryKey;
+pub struct LibraryKey;
 
 impl TypeMapKey for HttpKey {
     type Value = HttpClient;
 }
 
 impl TypeMapKey for MusicStateKey {
-    type Value = Arc<Mutex<BotMusicState>>;
+    type Value = Arc<BotMusicState>;
+}
+
+impl TypeMapKey for SourceRegistryKey {
+    type Value = Arc<SourceRegistry>;
+}
+
+impl TypeMapKey for LibraryKey {
+    type Value = Arc<Library>;
 }
 
 pub async fn start() -> serenity::Result<()> {
@@ -33,13 +46,44 @@ pub async fn start() -> serenity::Result<()> {
         | GatewayIntents::GUILDS
         | GatewayIntents::GUILD_VOICE_STATES;
 
+    // Local music library is optional, scan it in the background so login isn't delayed
+    let library = Library::from_env().await.map(Arc::new);
+    if let Some(library) = library.clone() {
+        tokio::spawn(async move {
+            if let Err(e) = library.rescan().await {
+                tracing::error!("Library scan failed: {:?}", e);
+            }
+        });
+    }
+
+    let music_state = Arc::new(BotMusicState::new());
+    let tokens = TokenRegistry::new();
+
     // Create a new instance of the Client, logging in as a bot.
-    let mut client = Client::builder(token, intents)
+    let mut builder = Client::builder(token, intents)
         .event_handler(Handler)
         .register_songbird()
         .type_map_insert::<HttpKey>(HttpClient::new())
-        .type_map_insert::<MusicStateKey>(Arc::new(Mutex::new(BotMusicState::new())))
-        .await?;
+        .type_map_insert::<MusicStateKey>(music_state.clone())
+        .type_map_insert::<SourceRegistryKey>(Arc::new(SourceRegistry::with_defaults(
+            tokens.clone(),
+            library.clone(),
+        )));
+
+    if let Some(library) = library {
+        builder = builder.type_map_insert::<LibraryKey>(library);
+    }
+
+    let mut client = builder.await?;
+
+    if let Some(addr) = server::addr_from_env() {
+        let status = server::Status {
+            music_state,
+            shard_manager: client.shard_manager.clone(),
+            tokens,
+        };
+        tokio::spawn(server::serve(addr, status));
+    }
 
     client.start().await
 }
@@ -1,4 +1,5 @@
 pub mod greeting;
 pub mod music;
+pub mod onboarding;
 pub mod voice;
 
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use serenity::all::GuildId;
+use serenity::{client::Context, model::prelude::Message};
+
+use crate::{
+    sources::{SourceRegistry, TrackInfo},
+    store::{history, settings},
+    utils::serenity_utils,
+};
+
+/// How many recent plays autoplay avoids repeating.
+const RECENT_WINDOW: usize = 50;
+/// How many related tracks are queued each time the queue runs out.
+const BATCH_SIZE: usize = 3;
+const CANDIDATES_PER_SEED: usize = 10;
+
+pub async fn toggle(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    if !serenity_utils::can_manage_guild(&ctx, &msg) {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            "Only members who can manage the server can change autoplay",
+            0xFF0000,
+        )
+        .await;
+        return;
+    }
+
+    let args = msg.content.strip_prefix("pb!autoplay").unwrap_or("").trim().to_lowercase();
+
+    let result = settings::update(guild_id, |s| {
+        s.autoplay = match args.as_str() {
+            "on" => true,
+            "off" => false,
+            _ => !s.autoplay,
+        };
+        s.autoplay
+    })
+    .await;
+
+    match result {
+        Ok(enabled) => {
+            let description = if enabled {
+                "📻 Autoplay is on, related tracks will play when the queue runs out"
+            } else {
+                "📻 Autoplay is off"
+            };
+            let _ = serenity_utils::send_embed(&ctx, &msg, description, 0x6C757D).await;
+        }
+        Err(e) => {
+            tracing::error!("Failed to save settings: {:?}", e);
+            let _ = serenity_utils::send_embed(&ctx, &msg, "Failed to change autoplay 😞", 0xFF0000)
+                .await;
+        }
+    }
+}
+
+fn title_key(track: &TrackInfo) -> String {
+    format!("{} {}", track.title, track.artist).to_lowercase()
+}
+
+/// Tracks related to `seeds` (most recent first) that weren't played recently.
+pub async fn pick(guild_id: GuildId, seeds: &[TrackInfo], sources: &SourceRegistry) -> Vec<TrackInfo> {
+    let recent = history::load(guild_id).await.unwrap_or_default();
+
+    let mut seen_urls: HashSet<String> = HashSet::new();
+    let mut seen_titles: HashSet<String> = HashSet::new();
+    for track in recent
+        .iter()
+        .rev()
+        .take(RECENT_WINDOW)
+        .map(|entry| &entry.track)
+        .chain(seeds)
+    {
+        seen_urls.insert(track.url.clone());
+        seen_titles.insert(title_key(track));
+    }
+
+    let mut picks = Vec::new();
+
+    for seed in seeds {
+        let candidates = match sources.related(seed, CANDIDATES_PER_SEED).await {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!("No related tracks for {}: {:?}", seed.url, e);
+                continue;
+            }
+        };
+
+        for candidate in candidates {
+            if candidate.is_live
+                || !seen_urls.insert(candidate.url.clone())
+                || !seen_titles.insert(title_key(&candidate))
+            {
+                continue;
+            }
+
+            picks.push(candidate);
+            if picks.len() >= BATCH_SIZE {
+                return picks;
+            }
+        }
+    }
+
+    picks
+}
@@ -1,82 +1,464 @@
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
+use reqwest::Client;
+use serenity::all::{ChannelId, Context, GuildId};
 use serenity::async_trait;
-use songbird::TrackEvent;
+use songbird::events::context_data::DisconnectReason;
 use songbird::events::{Event, EventContext, EventHandler};
+use songbird::tracks::{PlayMode, TrackHandle};
+use songbird::TrackEvent;
 use tokio::sync::Mutex;
 
+use crate::bot::{HttpKey, SourceRegistryKey};
 use crate::commands;
-use crate::commands::music::queue::BotMusicState;
+use crate::commands::music::{autoplay, history};
+use crate::commands::music::queue::{self, GuildMusicSession, SessionHandle, Started};
+use crate::metrics;
+use crate::sources::{SourceRegistry, TrackInfo, http};
+use crate::store::settings;
 use crate::utils::serenity_utils;
 
 pub struct OnEnd {
     pub ctx: serenity::all::Context,
-    pub msg: serenity::all::Message,
     pub guild_id: serenity::all::GuildId,
     pub call: Weak<Mutex<songbird::Call>>,
-    pub shared_state: Weak<Mutex<BotMusicState>>,
+    pub session: Weak<Mutex<GuildMusicSession>>,
 }
 
+/// How long to wait before reopening a live stream whose connection dropped, doubled
+/// for every drop in a row up to the maximum.
+const LIVE_RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const LIVE_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// A stream that played this long before dropping counts its drops afresh.
+const LIVE_STABLE_AFTER: Duration = Duration::from_secs(60);
+/// How often a radio stream is asked what it is playing.
+const STREAM_TITLE_INTERVAL: Duration = Duration::from_secs(30);
+/// Pause before opening a track again after it failed to.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// How many of the last queued tracks autoplay looks for related music from.
+const AUTOPLAY_SEEDS: usize = 3;
+/// How often to try getting a dropped voice connection back, and the first pause between tries.
+const REJOIN_ATTEMPTS: u32 = 5;
+const REJOIN_DELAY: Duration = Duration::from_secs(1);
+
 pub struct OnDisconnect {
     pub ctx: serenity::all::Context,
-    pub msg: serenity::all::Message,
 }
 
 #[async_trait]
 impl EventHandler for OnEnd {
-    async fn act(&self, _e_ctx: &EventContext<'_>) -> Option<Event> {
-        let shared_arc = self.shared_state.upgrade()?;
-        let mut shared = shared_arc.lock().await;
-        let guild = shared.music_sessions.get_mut(&self.guild_id)?;
+    #[tracing::instrument(name = "track_end", skip_all, fields(guild_id = %self.guild_id))]
+    async fn act(&self, e_ctx: &EventContext<'_>) -> Option<Event> {
+        let session = self.session.upgrade()?;
+
+        let (play_mode, position, ended) = match e_ctx {
+            EventContext::Track(tracks) => tracks
+                .first()
+                .map(|(state, handle)| (state.playing.clone(), state.position, handle.uuid()))?,
+            _ => return None,
+        };
+
+        // A live stream never ends on its own, if it did the connection dropped
+        let ended_naturally = matches!(play_mode, PlayMode::End);
+        let skipped = matches!(play_mode, PlayMode::Stop);
+        // songbird fires End alongside Error, so broken tracks are handled here too
+        let failed = match &play_mode {
+            PlayMode::Errored(err) => Some(err.to_string()),
+            _ => None,
+        };
+
+        let (http_client, sources) = {
+            let data = self.ctx.data.read().await;
+            (data.get::<HttpKey>().cloned()?, data.get::<SourceRegistryKey>().cloned()?)
+        };
 
-        let channel = &mut guild.voice_state;
+        // Tracks to seed autoplay with, once the last queued track has finished
+        let (reconnect_delay, gave_up, seeds, text_channel) = {
+            let mut guard = session.lock().await;
+            let text_channel = guard.text_channel;
+            let channel = &mut guard.voice_state;
 
-        if let Some((_, metadata)) = &channel.now_playing {
+            // Only the track that is still current moves the queue on
+            match &channel.now_playing {
+                Some((handle, _)) if handle.uuid() == ended => {}
+                _ => return None,
+            }
+            let (_, metadata) = channel.now_playing.take()?;
+            let reason = match (&failed, skipped) {
+                (Some(_), _) => "failed",
+                (None, true) => "skipped",
+                (None, false) => "finished",
+            };
+            metrics::TRACKS_ENDED.with_label_values(&[reason]).inc();
             if let Some(meta) = metadata {
                 tracing::info!("🎵 Finished playing {:?} {:?}", meta.title, meta.artist);
             };
 
-            channel.now_playing = None;
-            channel.index_playing += 1;
-
-
-            let track_handle = channel.playing_track().await;
-
-            match track_handle {
-                Some(handle) => {
-                    let shared_state = Arc::downgrade(&shared_arc);
-                    let call_shared = channel.call.as_ref().map(Arc::downgrade)?;
-
-                    let _ = handle.add_event(
-                        Event::Track(TrackEvent::End),
-                        OnEnd {
-                            ctx: self.ctx.clone(),
-                            msg: self.msg.clone(),
-                            guild_id: self.guild_id.clone(),
-                            call: call_shared,
-                            shared_state: shared_state.clone(),
-                        },
-                    );
+            let reconnect = ended_naturally
+                && channel
+                    .get_current_track()
+                    .is_some_and(|track| track.info.is_live);
+
+            let mut gave_up = None;
+            let mut advance = !reconnect;
+            let mut reconnect_delay = None;
+
+            if let Some(err) = &failed {
+                let track = channel.get_current_track()?;
+                tracing::error!("Playback of {} failed at {:?}: {}", track.info.url, position, err);
+
+                // Reopening resolves the URL again, then picks up where it broke off
+                track.resume_from = Some(position);
+                advance = false;
+                gave_up = channel.note_failure();
+            }
+
+            // A dead station would otherwise be reopened forever
+            if reconnect {
+                let track = channel.get_current_track()?;
+                if position >= LIVE_STABLE_AFTER {
+                    track.failures = 0;
                 }
-                None => {
-                    let _ = serenity_utils::send_embed(
-                        &self.ctx,
-                        &self.msg,
-                        "Next up is missing or unavailable",
-                        0xFF0000,
-                    )
-                    .await;
+
+                gave_up = channel.note_failure();
+                match (&gave_up, channel.get_current_track()) {
+                    (None, Some(track)) => {
+                        let backoff = 2u32.saturating_pow(track.failures.saturating_sub(1));
+                        let delay = LIVE_RECONNECT_DELAY
+                            .saturating_mul(backoff)
+                            .min(LIVE_RECONNECT_MAX_DELAY);
+                        tracing::warn!("Live stream dropped, reconnecting in {:?}", delay);
+                        reconnect_delay = Some(delay);
+                    }
+                    _ => tracing::warn!("Live stream keeps dropping, skipping it"),
                 }
             }
+
+            if advance {
+                if let Some(track) = channel.get_current_track() {
+                    history::record_play(self.guild_id, track, skipped);
+                }
+
+                channel.index_playing += 1;
+            }
+
+            let seeds = channel.is_exhausted().then(|| {
+                channel
+                    .queue
+                    .iter()
+                    .rev()
+                    .take(AUTOPLAY_SEEDS)
+                    .map(|q| q.info.clone())
+                    .collect::<Vec<_>>()
+            });
+
+            (reconnect_delay, gave_up, seeds, text_channel)
+        };
+
+        if let Some(track) = gave_up {
+            skip_notice(&self.ctx, text_channel, &track).await;
+        }
+
+        if let Some(delay) = reconnect_delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        // Related tracks are looked up without holding the session
+        let mut picks = Vec::new();
+        if let Some(seeds) = seeds
+            && settings::load(self.guild_id).await.autoplay
+        {
+            picks = autoplay::pick(self.guild_id, &seeds, &sources).await;
+        }
+
+        if let Some(first) = picks.first() {
+            let _ = serenity_utils::send_channel_embed(
+                &self.ctx,
+                text_channel,
+                &format!("📻 Autoplay: {} — {}", first.title, first.artist),
+                0x6C757D,
+            )
+            .await;
+        }
+
+        {
+            let mut guard = session.lock().await;
+            let bot_id = self.ctx.cache.current_user().id;
+            for pick in picks {
+                guard.voice_state.add_autoplay_track(pick, bot_id);
+            }
+
+            if guard.voice_state.is_exhausted() {
+                return None;
+            }
         }
+
+        start_playback(&self.ctx, self.guild_id, &session, &sources, http_client).await;
         None
     }
 }
 
+async fn skip_notice(ctx: &Context, channel_id: ChannelId, track: &TrackInfo) {
+    let _ = serenity_utils::send_channel_embed(
+        ctx,
+        channel_id,
+        &format!("⚠️ Skipped {} — {}, it keeps failing to play", track.title, track.artist),
+        0xFF0000,
+    )
+    .await;
+}
+
+/// Starts the current track and keeps the queue moving once it ends. A track that fails
+/// to open is retried a few times, then skipped with a notice.
+#[tracing::instrument(name = "session", skip_all, fields(guild_id = %guild_id))]
+pub async fn start_playback(
+    ctx: &Context,
+    guild_id: GuildId,
+    session: &SessionHandle,
+    sources: &SourceRegistry,
+    client: Client,
+) {
+    loop {
+        match queue::play_current(session, sources, client.clone()).await {
+            Started::Playing(handle) => {
+                let (call, radio) = {
+                    let guard = session.lock().await;
+                    guard.wake_prefetch();
+                    let state = &guard.voice_state;
+                    let radio = state
+                        .queue
+                        .get(state.index_playing)
+                        .filter(|t| t.info.is_live && t.info.source == "http")
+                        .map(|t| (t.id, t.info.url.clone()));
+                    (state.call.as_ref().map(Arc::downgrade).unwrap_or_default(), radio)
+                };
+
+                if let Some((id, url)) = radio {
+                    tokio::spawn(follow_stream_title(
+                        Arc::downgrade(session),
+                        id,
+                        handle.clone(),
+                        url,
+                        client.clone(),
+                    ));
+                }
+
+                let _ = handle.add_event(
+                    Event::Track(TrackEvent::End),
+                    OnEnd {
+                        ctx: ctx.clone(),
+                        guild_id,
+                        call,
+                        session: Arc::downgrade(session),
+                    },
+                );
+                return;
+            }
+            Started::AlreadyPlaying => return,
+            Started::Unavailable => {
+                let (gave_up, text_channel) = {
+                    let mut guard = session.lock().await;
+                    let text_channel = guard.text_channel;
+                    let channel = &mut guard.voice_state;
+                    if channel.call.is_none() || channel.is_exhausted() {
+                        return;
+                    }
+                    (channel.note_failure(), text_channel)
+                };
+
+                match gave_up {
+                    Some(track) => skip_notice(ctx, text_channel, &track).await,
+                    None => tokio::time::sleep(RETRY_DELAY).await,
+                }
+
+                let exhausted = session.lock().await.voice_state.is_exhausted();
+                if exhausted {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the title of a playing radio stream in step with the song it announces, until
+/// the stream stops playing.
+async fn follow_stream_title(
+    session: Weak<Mutex<GuildMusicSession>>,
+    id: u64,
+    playing: TrackHandle,
+    url: String,
+    client: Client,
+) {
+    loop {
+        tokio::time::sleep(STREAM_TITLE_INTERVAL).await;
+        let title = http::stream_title(&client, &url).await;
+
+        let Some(session) = session.upgrade() else {
+            return;
+        };
+        let mut guard = session.lock().await;
+        let state = &mut guard.voice_state;
+
+        let still_playing = state
+            .now_playing
+            .as_ref()
+            .is_some_and(|(handle, _)| handle.uuid() == playing.uuid());
+        if !still_playing {
+            return;
+        }
+
+        let Some(title) = title else {
+            continue;
+        };
+        if let Some(track) = state.get_current_track()
+            && track.id == id
+            && track.info.title != title
+        {
+            tracing::info!("{} is now playing {}", url, title);
+            track.info.title = title.clone();
+            if let Some((_, Some(metadata))) = &mut state.now_playing {
+                metadata.title = Some(title);
+            }
+        }
+    }
+}
+
 #[async_trait]
 impl EventHandler for OnDisconnect {
-    async fn act(&self, _e_ctx: &EventContext<'_>) -> Option<Event> {
-        commands::voice::leave(self.ctx.clone(), self.msg.clone()).await;
+    async fn act(&self, e_ctx: &EventContext<'_>) -> Option<Event> {
+        match e_ctx {
+            EventContext::DriverReconnect(data) => {
+                let guild_id = GuildId::from(data.guild_id.0);
+                tracing::info!("Voice connection in guild {} re-established", guild_id);
+
+                if let Some(channel_id) = data.channel_id {
+                    let session = queue::guild_session(&self.ctx, guild_id).await?;
+                    session.lock().await.channel_id = ChannelId::from(channel_id.0);
+                }
+            }
+            EventContext::DriverDisconnect(data) => {
+                // No reason means we left or moved on purpose
+                if matches!(data.reason, None | Some(DisconnectReason::Requested)) {
+                    return None;
+                }
+                let guild_id = GuildId::from(data.guild_id.0);
+                tracing::warn!("Voice connection in guild {} dropped: {:?}", guild_id, data.reason);
+
+                // Hold the current track where it is until we are back
+                let session = queue::guild_session(&self.ctx, guild_id).await?;
+                let handle = session
+                    .lock()
+                    .await
+                    .voice_state
+                    .now_playing
+                    .as_ref()
+                    .map(|(handle, _)| handle.clone());
+                let mut position = None;
+                if let Some(handle) = handle {
+                    position = handle.get_info().await.ok().map(|state| state.position);
+                    let _ = handle.pause();
+                }
+
+                tokio::spawn(rejoin(self.ctx.clone(), guild_id, position));
+            }
+            _ => {}
+        }
         None
     }
 }
+
+/// Reconnects to the session's channel with exponential backoff, keeping the queue, and
+/// carries on with the current track from `position`. Gives up and leaves after
+/// `REJOIN_ATTEMPTS` failures.
+#[tracing::instrument(skip_all, fields(guild_id = %guild_id))]
+async fn rejoin(ctx: Context, guild_id: GuildId, position: Option<Duration>) {
+    let manager = match songbird::get(&ctx).await {
+        Some(m) => m,
+        None => return,
+    };
+
+    let mut delay = REJOIN_DELAY;
+    let mut text_channel = None;
+    for attempt in 1..=REJOIN_ATTEMPTS {
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+
+        // Leaving, or being disconnected by a moderator, ends the session meanwhile
+        let session = match queue::guild_session(&ctx, guild_id).await {
+            Some(s) => s,
+            None => return,
+        };
+        let channel_id = {
+            let guard = session.lock().await;
+            text_channel = Some(guard.text_channel);
+            guard.channel_id
+        };
+
+        match manager.join(guild_id, channel_id).await {
+            Ok(_) => {
+                tracing::info!("Rejoined voice in guild {} after {} attempts", guild_id, attempt);
+                resume(&ctx, guild_id, &session, position).await;
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("Rejoin attempt {} in guild {} failed: {:?}", attempt, guild_id, e);
+            }
+        }
+    }
+
+    tracing::error!("Giving up on voice in guild {}", guild_id);
+    if let Some(text_channel) = text_channel {
+        let _ = serenity_utils::send_channel_embed(
+            &ctx,
+            text_channel,
+            "Lost the voice connection and could not get it back 😞",
+            0xFF0000,
+        )
+        .await;
+    }
+    commands::voice::teardown(&ctx, guild_id).await;
+}
+
+async fn resume(
+    ctx: &Context,
+    guild_id: GuildId,
+    session: &SessionHandle,
+    position: Option<Duration>,
+) {
+    let (http_client, sources) = {
+        let data = ctx.data.read().await;
+        match (data.get::<HttpKey>().cloned(), data.get::<SourceRegistryKey>().cloned()) {
+            (Some(client), Some(sources)) => (client, sources),
+            _ => return,
+        }
+    };
+
+    let handle = session
+        .lock()
+        .await
+        .voice_state
+        .now_playing
+        .as_ref()
+        .map(|(handle, _)| handle.clone());
+
+    // The paused track survives most reconnects, otherwise it is opened again
+    if let Some(handle) = handle
+        && handle.play().is_err()
+    {
+        let mut guard = session.lock().await;
+        let channel = &mut guard.voice_state;
+        if channel
+            .now_playing
+            .as_ref()
+            .is_some_and(|(current, _)| current.uuid() == handle.uuid())
+        {
+            channel.now_playing = None;
+            if let Some(track) = channel.get_current_track() {
+                track.resume_from = position;
+            }
+        }
+    }
+
+    start_playback(ctx, guild_id, session, &sources, http_client).await;
+}
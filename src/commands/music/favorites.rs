@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+
+use anyhow::{Error, Result};
+use rand::seq::SliceRandom;
+use serenity::{
+    all::{
+        ComponentInteraction, CreateInteractionResponse, CreateInteractionResponseMessage,
+        GuildId, MessageId, UserId,
+    },
+    client::Context,
+    model::prelude::Message,
+};
+
+use crate::{
+    commands::music::{queue, track},
+    sources::TrackInfo,
+    store::favorites,
+    utils::serenity_utils,
+};
+
+/// Custom id of the heart button under track cards.
+pub const LIKE_BUTTON: &str = "like";
+const PAGE_SIZE: usize = 10;
+/// How many track cards can still be liked, the oldest are forgotten first.
+const MAX_CARDS: usize = 500;
+
+/// Track of every recent card, by the message it was sent in.
+static CARDS: LazyLock<Mutex<VecDeque<(MessageId, TrackInfo)>>> = LazyLock::new(Default::default);
+
+async fn current_track(ctx: &Context, guild_id: GuildId) -> Option<TrackInfo> {
+    let session = queue::guild_session(ctx, guild_id).await?;
+    let mut guard = session.lock().await;
+    let channel_state = &mut guard.voice_state;
+    channel_state.now_playing.as_ref()?;
+    channel_state.get_current_track().map(|t| t.info.clone())
+}
+
+/// Remembers which track the card in `message_id` shows, so its heart likes that track.
+pub fn remember_card(message_id: MessageId, track: TrackInfo) {
+    let mut cards = CARDS.lock().unwrap();
+    if cards.len() >= MAX_CARDS {
+        cards.pop_front();
+    }
+    cards.push_back((message_id, track));
+}
+
+fn card_track(message_id: MessageId) -> Option<TrackInfo> {
+    let cards = CARDS.lock().unwrap();
+    cards.iter().find(|(id, _)| *id == message_id).map(|(_, track)| track.clone())
+}
+
+async fn load_tracks(guild_id: GuildId, user_id: UserId, server: bool) -> Result<Vec<TrackInfo>, Error> {
+    if server {
+        return favorites::load_guild_tracks(guild_id).await;
+    }
+
+    let favs = favorites::load_user(guild_id, user_id).await?;
+    Ok(favs.into_iter().map(|f| f.track).collect())
+}
+
+pub async fn like(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let track = match current_track(&ctx, guild_id).await {
+        Some(t) => t,
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "You're not playing any music", 0x6C757D)
+                .await;
+            return;
+        }
+    };
+
+    if !track.can_persist() {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            "Uploaded files can't be added to favorites, their links expire",
+            0x6C757D,
+        )
+        .await;
+        return;
+    }
+
+    let title = track.title.clone();
+    match favorites::like(guild_id, msg.author.id, track).await {
+        Ok(true) => {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &format!("❤️ Added {} to your favorites", title),
+                0x00AAFF,
+            )
+            .await;
+        }
+        Ok(false) => {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &format!("{} is already in your favorites", title),
+                0x6C757D,
+            )
+            .await;
+        }
+        Err(e) => {
+            tracing::error!("Failed to save favorite: {:?}", e);
+            let _ = serenity_utils::send_embed(&ctx, &msg, "Failed to save favorite 😞", 0xFF0000)
+                .await;
+        }
+    }
+}
+
+/// Likes or unlikes the track of the card whose heart was pressed, answering only the presser.
+pub async fn on_like_button(ctx: Context, component: ComponentInteraction) {
+    let guild_id = match component.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let content = match card_track(component.message.id) {
+        Some(track) => {
+            let title = track.title.clone();
+            match favorites::toggle(guild_id, component.user.id, track).await {
+                Ok(true) => format!("❤️ Added {} to your favorites", title),
+                Ok(false) => format!("💔 Removed {} from your favorites", title),
+                Err(e) => {
+                    tracing::error!("Failed to save favorite: {:?}", e);
+                    "Failed to save favorite 😞".to_string()
+                }
+            }
+        }
+        None => "This card is too old to like from, use pb!like while the track plays".to_string(),
+    };
+
+    let _ = component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await;
+}
+
+pub async fn list(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let args = msg.content.strip_prefix("pb!favorites").unwrap_or("").trim();
+    let server = args.split_whitespace().any(|w| w == "--server" || w == "-s");
+    let page = args
+        .split_whitespace()
+        .find_map(|w| w.parse::<usize>().ok())
+        .unwrap_or(1);
+
+    let heading = if server { "Server favorites" } else { "Your favorites" };
+
+    let tracks = match load_tracks(guild_id, msg.author.id, server).await {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("Failed to load favorites: {:?}", e);
+            let _ = serenity_utils::send_embed(&ctx, &msg, "Failed to load favorites 😞", 0xFF0000)
+                .await;
+            return;
+        }
+    };
+
+    if tracks.is_empty() {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            "No favorites yet, use pb!like or ❤️ on a track",
+            0x6C757D,
+        )
+        .await;
+        return;
+    }
+
+    let pages = tracks.len().div_ceil(PAGE_SIZE);
+    let page = page.clamp(1, pages);
+
+    let lines = tracks
+        .iter()
+        .enumerate()
+        .rev()
+        .skip((page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(i, t)| format!("`{}.` {} — {}", i + 1, t.title, t.artist))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let _ = serenity_utils::send_embed(
+        &ctx,
+        &msg,
+        &format!(
+            "**{}** — {} tracks\n\n{}\n\nPage {}/{}",
+            heading,
+            tracks.len(),
+            lines,
+            page,
+            pages
+        ),
+        0x00AAFF,
+    )
+    .await;
+}
+
+/// `pb!play favorites [--server]`, queues every liked track in random order.
+pub async fn play(ctx: Context, msg: Message, server: bool) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let mut tracks = match load_tracks(guild_id, msg.author.id, server).await {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::error!("Failed to load favorites: {:?}", e);
+            let _ = serenity_utils::send_embed(&ctx, &msg, "Failed to load favorites 😞", 0xFF0000)
+                .await;
+            return;
+        }
+    };
+
+    if tracks.is_empty() {
+        let _ = serenity_utils::send_embed(&ctx, &msg, "There are no favorites to play", 0x6C757D)
+            .await;
+        return;
+    }
+
+    tracks.shuffle(&mut rand::thread_rng());
+    track::enqueue(ctx, msg, tracks).await;
+}
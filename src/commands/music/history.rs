@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use serenity::{all::GuildId, client::Context, model::prelude::Message};
+
+use crate::{
+    bot::{HttpKey, SourceRegistryKey},
+    commands::{
+        self,
+        music::{
+            event,
+            queue::{self, QueuedTrack},
+        },
+    },
+    store::{
+        self,
+        history::{self, HistoryEntry},
+    },
+    utils::serenity_utils,
+};
+
+const PAGE_SIZE: usize = 10;
+
+/// Writes the play of `track` that ends now to the guild's history in the background.
+/// Uploads are left out, their links expire.
+pub fn record_play(guild_id: GuildId, track: &QueuedTrack, skipped: bool) {
+    if !track.info.can_persist() {
+        return;
+    }
+
+    let entry = HistoryEntry {
+        track: track.info.clone(),
+        requested_by: track.requested_by,
+        started_at: track.started_at.unwrap_or_default(),
+        ended_at: store::unix_now(),
+        skipped,
+    };
+
+    tokio::spawn(async move {
+        if let Err(e) = history::record(guild_id, entry).await {
+            tracing::error!("Failed to record history: {:?}", e);
+        }
+    });
+}
+
+pub async fn history(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let entries = match history::load(guild_id).await {
+        Ok(e) => e,
+        Err(e) => {
+            tracing::error!("Failed to load history: {:?}", e);
+            let _ =
+                serenity_utils::send_embed(&ctx, &msg, "Failed to load history 😞", 0xFF0000).await;
+            return;
+        }
+    };
+
+    if entries.is_empty() {
+        let _ =
+            serenity_utils::send_embed(&ctx, &msg, "Nothing has been played yet", 0x6C757D).await;
+        return;
+    }
+
+    let args = msg.content.strip_prefix("pb!history").unwrap_or("").trim();
+    let pages = entries.len().div_ceil(PAGE_SIZE);
+    let page = args.parse::<usize>().unwrap_or(1).clamp(1, pages);
+
+    let lines = entries
+        .iter()
+        .rev()
+        .enumerate()
+        .skip((page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(i, entry)| {
+            format!(
+                "`{}.` {} — {} · <@{}> · <t:{}:R>{}",
+                i + 1,
+                entry.track.title,
+                entry.track.artist,
+                entry.requested_by,
+                entry.started_at,
+                if entry.skipped {
+                    " · ⏭️ skipped"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let _ = serenity_utils::send_embed(
+        &ctx,
+        &msg,
+        &format!(
+            "**Recently played**\n\n{}\n\nPage {}/{}",
+            lines, page, pages
+        ),
+        0x00AAFF,
+    )
+    .await;
+}
+
+/// Plays the track before the current one again, right now. Used repeatedly it keeps
+/// walking back through the history.
+pub async fn previous(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    if queue::guild_session(&ctx, guild_id).await.is_none() {
+        commands::voice::join(ctx.clone(), msg.clone()).await;
+    }
+    let session = match queue::guild_session(&ctx, guild_id).await {
+        Some(s) => s,
+        None => return,
+    };
+
+    // A track brought back by an earlier pb!previous continues from its own entry
+    let cursor = {
+        let mut guard = session.lock().await;
+        guard
+            .voice_state
+            .get_current_track()
+            .and_then(|t| t.rewound_to.map(|at| (at, t.info.url.clone())))
+    };
+
+    let entries = match history::load(guild_id).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!("Failed to load history: {:?}", e);
+            Vec::new()
+        }
+    };
+    let end = match &cursor {
+        Some((at, url)) => entries
+            .iter()
+            .rposition(|e| e.started_at == *at && e.track.url == *url)
+            .unwrap_or(0),
+        None => entries.len(),
+    };
+
+    let entry = match entries[..end].last() {
+        Some(e) => e.clone(),
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "There is no previous track", 0x6C757D)
+                .await;
+            return;
+        }
+    };
+
+    // Slot it in after the current track and stop the current one, OnEnd moves on to it
+    let (stop_current, start) = {
+        let mut guard = session.lock().await;
+        let channel_state = &mut guard.voice_state;
+
+        channel_state
+            .insert_next_lazy(entry.track.clone(), msg.author.id)
+            .rewound_to = Some(entry.started_at);
+
+        let stop_current = channel_state.now_playing.as_ref().map(|(h, _)| h.clone());
+        (stop_current, channel_state.is_idle())
+    };
+
+    if let Some(handle) = stop_current {
+        let _ = handle.stop();
+    }
+
+    let _ = serenity_utils::send_embed(
+        &ctx,
+        &msg,
+        &format!("⏮️ Back to {}", entry.track.title),
+        0x6C757D,
+    )
+    .await;
+
+    if start {
+        let (sources, http_client) = {
+            let data = ctx.data.read().await;
+            (
+                data.get::<SourceRegistryKey>()
+                    .cloned()
+                    .expect("Guaranteed to exist in the typemap."),
+                data.get::<HttpKey>()
+                    .cloned()
+                    .expect("Guaranteed to exist in the typemap."),
+            )
+        };
+        event::start_playback(&ctx, guild_id, &session, &sources, http_client).await;
+    }
+
+    session.lock().await.wake_prefetch();
+}
+
+/// Restarts the current track from the beginning.
+pub async fn replay(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let session = match queue::guild_session(&ctx, guild_id).await {
+        Some(s) => s,
+        None => return,
+    };
+
+    let current = {
+        let mut guard = session.lock().await;
+        let channel_state = &mut guard.voice_state;
+
+        channel_state.now_playing.as_ref().map(|(h, _)| h.clone()).and_then(|handle| {
+            channel_state
+                .get_current_track()
+                .map(|track| (handle, track.info.title.clone(), track.info.is_live))
+        })
+    };
+
+    let (track_handle, title, is_live) = match current {
+        Some(c) => c,
+        None => {
+            let _ =
+                serenity_utils::send_embed(&ctx, &msg, "You're not playing any music", 0x6C757D)
+                    .await;
+            return;
+        }
+    };
+
+    if is_live {
+        let _ = serenity_utils::send_embed(&ctx, &msg, "Live streams can't be replayed", 0xFF0000)
+            .await;
+        return;
+    }
+
+    match track_handle.seek_async(Duration::ZERO).await {
+        Ok(_) => {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &format!("🔁 Replaying {}", title),
+                0x6C757D,
+            )
+            .await;
+        }
+        Err(e) => {
+            tracing::error!("Failed to seek to start: {:?}", e);
+            let _ =
+                serenity_utils::send_embed(&ctx, &msg, "Failed to replay this track 😞", 0xFF0000)
+                    .await;
+        }
+    }
+}
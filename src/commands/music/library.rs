@@ -0,0 +1,71 @@
+use serenity::{client::Context, model::prelude::Message};
+
+use crate::{bot::LibraryKey, commands::music::track, utils::serenity_utils};
+
+pub async fn local(ctx: Context, msg: Message) {
+    let args = msg.content.strip_prefix("pb!local").unwrap_or("").trim().to_string();
+
+    if args.is_empty() {
+        let _ = serenity_utils::send_embed(&ctx, &msg, "Usage: pb!local <query>", 0x6C757D).await;
+        return;
+    }
+
+    if ctx.data.read().await.get::<LibraryKey>().is_none() {
+        let _ = serenity_utils::send_embed(&ctx, &msg, "No local library configured", 0xFF0000).await;
+        return;
+    }
+
+    track::play_query(ctx, msg, &format!("local:{}", args)).await;
+}
+
+pub async fn library(ctx: Context, msg: Message) {
+    let args = msg.content.strip_prefix("pb!library").unwrap_or("").trim();
+
+    if args != "rescan" {
+        let _ = serenity_utils::send_embed(&ctx, &msg, "Usage: pb!library rescan", 0x6C757D).await;
+        return;
+    }
+
+    // A rescan walks the whole disk, so it is not for everyone to trigger
+    if !serenity_utils::can_manage_guild(&ctx, &msg) {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            "Only members who can manage the server can rescan the library",
+            0xFF0000,
+        )
+        .await;
+        return;
+    }
+
+    let library = match ctx.data.read().await.get::<LibraryKey>().cloned() {
+        Some(l) => l,
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "No local library configured", 0xFF0000)
+                .await;
+            return;
+        }
+    };
+
+    let _ = serenity_utils::send_embed(&ctx, &msg, "🔎 Rescanning the library...", 0x6C757D).await;
+
+    match library.rescan().await {
+        Ok(summary) => {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &format!(
+                    "📚 Library updated: {} added, {} updated, {} removed ({} tracks)",
+                    summary.added, summary.updated, summary.removed, summary.total
+                ),
+                0x00AAFF,
+            )
+            .await;
+        }
+        Err(e) => {
+            tracing::error!("Library rescan failed: {:?}", e);
+            let _ = serenity_utils::send_embed(&ctx, &msg, "Failed to rescan the library 😞", 0xFF0000)
+                .await;
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use serenity::{client::Context, model::prelude::Message};
+
+use crate::{
+    store::settings::{self, QueueLimits},
+    utils::serenity_utils,
+};
+
+const USAGE: &str = "Usage: pb!limits [queue|user|duration <number|off>] [duplicates|fair <on|off>]";
+
+pub async fn run(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let args = msg.content.strip_prefix("pb!limits").unwrap_or("").trim().to_lowercase();
+    let words: Vec<&str> = args.split_whitespace().collect();
+
+    let (name, value) = match words.as_slice() {
+        [] => {
+            let limits = settings::load(guild_id).await.limits;
+            let _ = serenity_utils::send_embed(&ctx, &msg, &describe(&limits), 0x6C757D).await;
+            return;
+        }
+        [name, value] => (*name, *value),
+        _ => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, USAGE, 0x6C757D).await;
+            return;
+        }
+    };
+
+    if !serenity_utils::can_manage_guild(&ctx, &msg) {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            "Only members who can manage the server can change queue limits",
+            0xFF0000,
+        )
+        .await;
+        return;
+    }
+
+    let number = match value {
+        "off" | "none" | "0" => Some(None),
+        v => v.parse::<usize>().ok().map(Some),
+    };
+    let switch = match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    };
+
+    let result = settings::update(guild_id, |s| {
+        let limits = &mut s.limits;
+        match (name, number, switch) {
+            ("queue", Some(n), _) => limits.max_queue_length = n,
+            ("user", Some(n), _) => limits.max_tracks_per_user = n,
+            ("duration", Some(n), _) => limits.max_track_duration = n.map(|secs| secs as u32),
+            ("duplicates", _, Some(allow)) => limits.no_duplicates = !allow,
+            ("fair", _, Some(on)) => limits.fair_queue = on,
+            _ => return None,
+        }
+        Some(limits.clone())
+    })
+    .await;
+
+    match result {
+        Ok(Some(limits)) => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, &describe(&limits), 0x00AAFF).await;
+        }
+        Ok(None) => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, USAGE, 0x6C757D).await;
+        }
+        Err(e) => {
+            tracing::error!("Failed to save settings: {:?}", e);
+            let _ =
+                serenity_utils::send_embed(&ctx, &msg, "Failed to change limits 😞", 0xFF0000).await;
+        }
+    }
+}
+
+fn describe(limits: &QueueLimits) -> String {
+    let limit = |value: Option<usize>| value.map_or("unlimited".to_string(), |v| v.to_string());
+    let switch = |on: bool| if on { "on" } else { "off" };
+
+    format!(
+        "**Queue limits**\n\nQueue length: {}\nTracks per member: {}\nTrack length: {}\nDuplicates: {}\nFair queue: {}",
+        limit(limits.max_queue_length),
+        limit(limits.max_tracks_per_user),
+        limits
+            .max_track_duration
+            .map_or("unlimited".to_string(), |secs| serenity_utils::format_duration(secs * 1000)),
+        switch(!limits.no_duplicates),
+        switch(limits.fair_queue),
+    )
+}
@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use serenity::{
+    all::{CreateEmbedFooter, EditMessage},
+    builder::{CreateEmbed, CreateMessage},
+    client::Context,
+    model::prelude::Message,
+};
+use songbird::tracks::{TrackHandle, TrackState};
+
+use crate::{
+    api::lyrics,
+    commands::music::queue,
+    models::lyrics::Lyrics,
+    sources::TrackInfo,
+    utils::serenity_utils,
+};
+
+const PAGE_LINES: usize = 25;
+/// Lines shown around the current one while following playback.
+const FOLLOW_BEFORE: usize = 2;
+const FOLLOW_AFTER: usize = 6;
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(2);
+
+pub async fn run(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let args = msg.content.strip_prefix("pb!lyrics").unwrap_or("").trim().to_lowercase();
+
+    let current = match queue::guild_session(&ctx, guild_id).await {
+        Some(session) => {
+            let mut guard = session.lock().await;
+            let channel_state = &mut guard.voice_state;
+            channel_state
+                .now_playing
+                .as_ref()
+                .map(|(handle, _)| handle.clone())
+                .zip(channel_state.get_current_track().map(|t| t.info.clone()))
+        }
+        None => None,
+    };
+
+    let (handle, track) = match current {
+        Some(c) => c,
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "You're not playing any music", 0x6C757D)
+                .await;
+            return;
+        }
+    };
+
+    let provider = match lyrics::configured_provider() {
+        Some(p) => p,
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "Lyrics are turned off", 0x6C757D).await;
+            return;
+        }
+    };
+
+    let artist = track.artist.trim_end_matches(" - Topic");
+    let found = match provider.fetch(&track.title, artist, track.duration_ms).await {
+        Ok(found) => found,
+        Err(e) => {
+            tracing::error!("Failed to fetch lyrics from {}: {:?}", provider.name(), e);
+            let _ =
+                serenity_utils::send_embed(&ctx, &msg, "Failed to load lyrics 😞", 0xFF0000).await;
+            return;
+        }
+    };
+
+    let lyrics = match found {
+        Some(l) if !l.lines.is_empty() => l,
+        _ => {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &format!("No lyrics found for {} — {}", track.title, artist),
+                0x6C757D,
+            )
+            .await;
+            return;
+        }
+    };
+
+    if args == "follow" {
+        if track.is_live || !lyrics.is_synced() {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                "These lyrics have no timings to follow along with",
+                0x6C757D,
+            )
+            .await;
+            return;
+        }
+
+        follow(ctx, msg, handle, track, lyrics, provider.name()).await;
+        return;
+    }
+
+    let pages = lyrics.lines.len().div_ceil(PAGE_LINES);
+    let page = args.parse::<usize>().unwrap_or(1).clamp(1, pages);
+
+    let body = lyrics
+        .lines
+        .iter()
+        .skip((page - 1) * PAGE_LINES)
+        .take(PAGE_LINES)
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let embed = lyrics_embed(
+        &track,
+        &body,
+        &format!("Page {}/{} · Lyrics from {}", page, pages, provider.name()),
+    );
+    let _ = msg
+        .channel_id
+        .send_message(&ctx.http, CreateMessage::default().embed(embed))
+        .await;
+}
+
+/// Keeps editing one message so the line being sung stays highlighted.
+async fn follow(
+    ctx: Context,
+    msg: Message,
+    handle: TrackHandle,
+    track: TrackInfo,
+    lyrics: Lyrics,
+    provider: &'static str,
+) {
+    let footer = format!("Following playback · Lyrics from {}", provider);
+
+    let mut current = handle.get_info().await.ok().and_then(|info| line_at(&lyrics, &info));
+    let embed = lyrics_embed(&track, &window(&lyrics, current), &footer);
+
+    let mut message = match msg
+        .channel_id
+        .send_message(&ctx.http, CreateMessage::default().embed(embed))
+        .await
+    {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::error!("Failed to send lyrics: {:?}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(FOLLOW_INTERVAL).await;
+
+            // The handle stops answering once the track has ended or was skipped
+            let info = match handle.get_info().await {
+                Ok(info) => info,
+                Err(_) => break,
+            };
+
+            let line = line_at(&lyrics, &info);
+            if line == current {
+                continue;
+            }
+            current = line;
+
+            let embed = lyrics_embed(&track, &window(&lyrics, current), &footer);
+            if let Err(e) = message
+                .edit(&ctx.http, EditMessage::new().embed(embed))
+                .await
+            {
+                tracing::warn!("Stopped following lyrics: {:?}", e);
+                break;
+            }
+        }
+    });
+}
+
+fn line_at(lyrics: &Lyrics, info: &TrackState) -> Option<usize> {
+    lyrics.line_at(info.position.as_millis() as u32)
+}
+
+fn window(lyrics: &Lyrics, current: Option<usize>) -> String {
+    let start = current.map(|c| c.saturating_sub(FOLLOW_BEFORE)).unwrap_or(0);
+
+    lyrics
+        .lines
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(FOLLOW_BEFORE + 1 + FOLLOW_AFTER)
+        .map(|(i, line)| match line.text.as_str() {
+            "" => "♪".to_string(),
+            text if Some(i) == current => format!("**▶ {}**", text),
+            text => text.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn lyrics_embed(track: &TrackInfo, body: &str, footer: &str) -> CreateEmbed {
+    CreateEmbed::new()
+        .title(format!("🎤 {} — {}", track.title, track.artist))
+        .description(body)
+        .footer(CreateEmbedFooter::new(footer))
+        .color(0x00AAFF)
+}
@@ -1,3 +1,11 @@
+pub mod autoplay;
 pub mod event;
+pub mod favorites;
+pub mod history;
+pub mod library;
+pub mod limits;
+pub mod lyrics;
+pub mod playlist;
+pub mod prefetch;
 pub mod track;
-pub mod queue;
\ No newline at end of file
+pub mod queue;
@@ -0,0 +1,482 @@
+use anyhow::{Error, Result};
+use serenity::{client::Context, model::prelude::Message};
+
+use crate::{
+    bot::SourceRegistryKey,
+    commands::music::{queue, track},
+    sources::TrackInfo,
+    store::playlist::{self, Playlist, PlaylistScope},
+    utils::serenity_utils,
+};
+
+const USAGE: &str =
+    "Usage: pb!playlist <save|load|list|show|delete|add|remove|import> [--server] <name> [...]";
+const PAGE_SIZE: usize = 10;
+
+pub async fn run(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let args = msg
+        .content
+        .strip_prefix("pb!playlist")
+        .unwrap_or("")
+        .trim()
+        .to_string();
+
+    // `--server` switches every subcommand to the playlists shared by the whole server
+    let mut scope = PlaylistScope::User(msg.author.id);
+    let mut words = Vec::new();
+    for word in args.split_whitespace() {
+        match word {
+            "--server" | "-s" => scope = PlaylistScope::Guild(guild_id),
+            _ => words.push(word),
+        }
+    }
+
+    let sub = words.first().copied().unwrap_or_default().to_lowercase();
+    let name = words.get(1).copied();
+    let rest = words.iter().skip(2).copied().collect::<Vec<_>>().join(" ");
+
+    let result = match (sub.as_str(), name) {
+        ("list", _) => list(&ctx, &msg).await,
+        ("save", Some(name)) => save(&ctx, &msg, scope, name).await,
+        ("load", Some(name)) => load(ctx.clone(), msg.clone(), scope, name).await,
+        ("show", Some(name)) => show(&ctx, &msg, scope, name, &rest).await,
+        ("delete", Some(name)) => delete(&ctx, &msg, scope, name).await,
+        ("add", Some(name)) if !rest.is_empty() => add(&ctx, &msg, scope, name, &rest).await,
+        ("remove", Some(name)) => remove(&ctx, &msg, scope, name, &rest).await,
+        ("import", Some(name)) if !rest.is_empty() => import(&ctx, &msg, scope, name, &rest).await,
+        _ => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, USAGE, 0x6C757D).await;
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::error!("Playlist command failed: {:?}", e);
+        let _ =
+            serenity_utils::send_embed(&ctx, &msg, "Playlist command failed 😞", 0xFF0000).await;
+    }
+}
+
+fn track_line(position: usize, track: &TrackInfo) -> String {
+    let length = match (track.is_live, track.duration_ms) {
+        (true, _) => "LIVE".to_string(),
+        (false, Some(ms)) => serenity_utils::format_duration(ms),
+        (false, None) => "--:--".to_string(),
+    };
+
+    format!(
+        "`{}.` {} — {} ({})",
+        position, track.title, track.artist, length
+    )
+}
+
+const UPLOADS_NOT_SAVED: &str = "Uploaded files can't be saved in playlists, their links expire";
+
+/// Note about uploaded files left out of a playlist, empty when there were none.
+fn skipped_uploads(count: usize) -> String {
+    match count {
+        0 => String::new(),
+        _ => format!("\n{}", UPLOADS_NOT_SAVED),
+    }
+}
+
+async fn not_found(
+    ctx: &Context,
+    msg: &Message,
+    scope: PlaylistScope,
+    name: &str,
+) -> Result<(), Error> {
+    serenity_utils::send_embed(
+        ctx,
+        msg,
+        &format!("No {} playlist named **{}**", scope.label(), name),
+        0xFF0000,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Whether the author of `msg` may change `playlist`. Personal playlists belong to their
+/// owner, server ones to whoever created them, the server owner and server managers.
+fn may_edit(ctx: &Context, msg: &Message, scope: PlaylistScope, playlist: &Playlist) -> bool {
+    if matches!(scope, PlaylistScope::User(_)) || playlist.created_by == msg.author.id {
+        return true;
+    }
+
+    let is_owner = msg
+        .guild_id
+        .and_then(|g| {
+            ctx.cache
+                .guild(g)
+                .map(|guild| guild.owner_id == msg.author.id)
+        })
+        .unwrap_or(false);
+
+    is_owner || serenity_utils::can_manage_guild(ctx, msg)
+}
+
+async fn forbidden(ctx: &Context, msg: &Message, name: &str) -> Result<(), Error> {
+    serenity_utils::send_embed(
+        ctx,
+        msg,
+        &format!("Only the creator of **{}** or a server manager can change it", name),
+        0xFF0000,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn save(ctx: &Context, msg: &Message, scope: PlaylistScope, name: &str) -> Result<(), Error> {
+    let guild_id = msg.guild_id.unwrap_or_default();
+
+    let mut tracks: Vec<TrackInfo> = match queue::guild_session(ctx, guild_id).await {
+        Some(session) => session
+            .lock()
+            .await
+            .voice_state
+            .queue
+            .iter()
+            .map(|q| q.info.clone())
+            .collect(),
+        None => Vec::new(),
+    };
+    let queued = tracks.len();
+    tracks.retain(TrackInfo::can_persist);
+
+    if tracks.is_empty() {
+        serenity_utils::send_embed(ctx, msg, "There is nothing in the queue to save", 0xFF0000)
+            .await?;
+        return Ok(());
+    }
+
+    let count = tracks.len();
+    let playlist = Playlist {
+        name: name.to_string(),
+        created_by: msg.author.id,
+        tracks,
+    };
+
+    if !replace(ctx, msg, scope, playlist).await? {
+        return forbidden(ctx, msg, name).await;
+    }
+
+    serenity_utils::send_embed(
+        ctx,
+        msg,
+        &format!(
+            "💾 Saved {} tracks to {} playlist **{}**{}",
+            count,
+            scope.label(),
+            name,
+            skipped_uploads(queued - count)
+        ),
+        0x00AAFF,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Stores `playlist`, overwriting one of the same name if the author may change it.
+/// Returns whether it was stored.
+async fn replace(
+    ctx: &Context,
+    msg: &Message,
+    scope: PlaylistScope,
+    playlist: Playlist,
+) -> Result<bool, Error> {
+    playlist::update(scope, |playlists| {
+        let key = playlist::key(&playlist.name);
+        if playlists.get(&key).is_some_and(|p| !may_edit(ctx, msg, scope, p)) {
+            return false;
+        }
+        playlists.insert(key, playlist);
+        true
+    })
+    .await
+}
+
+async fn load(ctx: Context, msg: Message, scope: PlaylistScope, name: &str) -> Result<(), Error> {
+    let found = playlist::load(scope).await?.remove(&playlist::key(name));
+
+    match found {
+        Some(playlist) if !playlist.tracks.is_empty() => {
+            track::enqueue(ctx, msg, playlist.tracks).await;
+            Ok(())
+        }
+        Some(_) => {
+            serenity_utils::send_embed(&ctx, &msg, &format!("**{}** is empty", name), 0xFF0000)
+                .await?;
+            Ok(())
+        }
+        None => not_found(&ctx, &msg, scope, name).await,
+    }
+}
+
+async fn list(ctx: &Context, msg: &Message) -> Result<(), Error> {
+    let guild_id = msg.guild_id.unwrap_or_default();
+    let mut description = String::new();
+
+    for scope in [
+        PlaylistScope::User(msg.author.id),
+        PlaylistScope::Guild(guild_id),
+    ] {
+        let playlists = playlist::load(scope).await?;
+
+        description.push_str(&format!("**{} playlists**\n", scope.label()));
+        if playlists.is_empty() {
+            description.push_str("_none yet_\n");
+        }
+        for playlist in playlists.values() {
+            description.push_str(&format!(
+                "• {} ({} tracks)\n",
+                playlist.name,
+                playlist.tracks.len()
+            ));
+        }
+        description.push('\n');
+    }
+
+    serenity_utils::send_embed(ctx, msg, &description, 0x00AAFF).await?;
+    Ok(())
+}
+
+async fn show(
+    ctx: &Context,
+    msg: &Message,
+    scope: PlaylistScope,
+    name: &str,
+    page: &str,
+) -> Result<(), Error> {
+    let playlist = match playlist::load(scope).await?.remove(&playlist::key(name)) {
+        Some(p) => p,
+        None => return not_found(ctx, msg, scope, name).await,
+    };
+
+    let pages = playlist.tracks.len().div_ceil(PAGE_SIZE).max(1);
+    let page = page.parse::<usize>().unwrap_or(1).clamp(1, pages);
+
+    let lines = playlist
+        .tracks
+        .iter()
+        .enumerate()
+        .skip((page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(i, t)| track_line(i + 1, t))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    serenity_utils::send_embed(
+        ctx,
+        msg,
+        &format!(
+            "**{}** — {} tracks\n\n{}\n\nPage {}/{}",
+            playlist.name,
+            playlist.tracks.len(),
+            lines,
+            page,
+            pages
+        ),
+        0x00AAFF,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn delete(
+    ctx: &Context,
+    msg: &Message,
+    scope: PlaylistScope,
+    name: &str,
+) -> Result<(), Error> {
+    let removed = playlist::update(scope, |playlists| {
+        let key = playlist::key(name);
+        match playlists.get(&key) {
+            Some(p) if !may_edit(ctx, msg, scope, p) => Err(()),
+            Some(_) => Ok(playlists.remove(&key)),
+            None => Ok(None),
+        }
+    })
+    .await?;
+
+    match removed {
+        Ok(Some(_)) => {
+            serenity_utils::send_embed(ctx, msg, &format!("🗑️ Deleted **{}**", name), 0x6C757D)
+                .await?;
+            Ok(())
+        }
+        Ok(None) => not_found(ctx, msg, scope, name).await,
+        Err(()) => forbidden(ctx, msg, name).await,
+    }
+}
+
+async fn add(
+    ctx: &Context,
+    msg: &Message,
+    scope: PlaylistScope,
+    name: &str,
+    query: &str,
+) -> Result<(), Error> {
+    let sources = ctx
+        .data
+        .read()
+        .await
+        .get::<SourceRegistryKey>()
+        .cloned()
+        .expect("Guaranteed to exist in the typemap.");
+    let mut tracks = sources.resolve(query).await?;
+
+    if tracks.is_empty() {
+        serenity_utils::send_embed(ctx, msg, "Could not find the provided song", 0xFF0000).await?;
+        return Ok(());
+    }
+
+    tracks.retain(TrackInfo::can_persist);
+    if tracks.is_empty() {
+        serenity_utils::send_embed(ctx, msg, UPLOADS_NOT_SAVED, 0xFF0000).await?;
+        return Ok(());
+    }
+
+    let added = tracks.len();
+    let author = msg.author.id;
+    let total = playlist::update(scope, |playlists| {
+        let playlist = playlists
+            .entry(playlist::key(name))
+            .or_insert_with(|| Playlist {
+                name: name.to_string(),
+                created_by: author,
+                tracks: Vec::new(),
+            });
+        if !may_edit(ctx, msg, scope, playlist) {
+            return None;
+        }
+        playlist.tracks.extend(tracks);
+        Some(playlist.tracks.len())
+    })
+    .await?;
+
+    let Some(total) = total else {
+        return forbidden(ctx, msg, name).await;
+    };
+
+    serenity_utils::send_embed(
+        ctx,
+        msg,
+        &format!(
+            "➕ Added {} tracks to **{}** ({} total)",
+            added, name, total
+        ),
+        0x00AAFF,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn remove(
+    ctx: &Context,
+    msg: &Message,
+    scope: PlaylistScope,
+    name: &str,
+    position: &str,
+) -> Result<(), Error> {
+    let position = match position.parse::<usize>() {
+        Ok(p) if p > 0 => p,
+        _ => {
+            serenity_utils::send_embed(
+                ctx,
+                msg,
+                "Usage: pb!playlist remove <name> <position>",
+                0x6C757D,
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let removed = playlist::update(scope, |playlists| {
+        playlists.get_mut(&playlist::key(name)).map(|p| {
+            if !may_edit(ctx, msg, scope, p) {
+                return Err(());
+            }
+            Ok((position <= p.tracks.len()).then(|| p.tracks.remove(position - 1)))
+        })
+    })
+    .await?;
+
+    match removed {
+        Some(Err(())) => forbidden(ctx, msg, name).await,
+        Some(Ok(Some(track))) => {
+            serenity_utils::send_embed(
+                ctx,
+                msg,
+                &format!("➖ Removed {} from **{}**", track.title, name),
+                0x6C757D,
+            )
+            .await?;
+            Ok(())
+        }
+        Some(Ok(None)) => {
+            serenity_utils::send_embed(ctx, msg, "There is no track at that position", 0xFF0000)
+                .await?;
+            Ok(())
+        }
+        None => not_found(ctx, msg, scope, name).await,
+    }
+}
+
+async fn import(
+    ctx: &Context,
+    msg: &Message,
+    scope: PlaylistScope,
+    name: &str,
+    url: &str,
+) -> Result<(), Error> {
+    if !url.contains("open.spotify.com/playlist/") {
+        serenity_utils::send_embed(
+            ctx,
+            msg,
+            "Only Spotify playlist links can be imported",
+            0xFF0000,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let sources = ctx
+        .data
+        .read()
+        .await
+        .get::<SourceRegistryKey>()
+        .cloned()
+        .expect("Guaranteed to exist in the typemap.");
+    let tracks = sources.resolve(url).await?;
+    let count = tracks.len();
+
+    let playlist = Playlist {
+        name: name.to_string(),
+        created_by: msg.author.id,
+        tracks,
+    };
+
+    if !replace(ctx, msg, scope, playlist).await? {
+        return forbidden(ctx, msg, name).await;
+    }
+
+    serenity_utils::send_embed(
+        ctx,
+        msg,
+        &format!(
+            "📥 Imported {} tracks into {} playlist **{}**",
+            count,
+            scope.label(),
+            name
+        ),
+        0x00AAFF,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use reqwest::Client;
+use songbird::input::{
+    Input,
+    codecs::{CODEC_REGISTRY, PROBE},
+};
+use tokio::sync::{Mutex, Notify, Semaphore};
+use tokio::task::{self, JoinHandle, JoinSet};
+
+use crate::commands::music::queue::GuildMusicSession;
+use crate::sources::{SourceRegistry, TrackInfo};
+
+/// How often the worker looks for expired inputs and retries that are due.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Tuning for the prefetch worker, read from the environment.
+#[derive(Debug, Clone, Copy)]
+pub struct PrefetchConfig {
+    /// How many upcoming tracks are kept resolved, `PREFETCH_DEPTH` (default 2).
+    pub depth: usize,
+    /// Resolutions running at once per guild, `PREFETCH_CONCURRENCY` (default 2).
+    pub concurrency: usize,
+    /// Attempts per track before giving up, `PREFETCH_RETRIES` (default 3).
+    pub attempts: u32,
+    /// Resolved stream URLs expire, inputs older than `PREFETCH_TTL_SECS` (default 1200) are redone.
+    pub ttl: Duration,
+}
+
+/// A track the worker was asked to resolve, identified by `QueuedTrack::id`.
+pub struct PrefetchJob {
+    pub id: u64,
+    pub info: TrackInfo,
+}
+
+/// Keeps the next few tracks of one guild resolved in the background.
+/// Dropping it stops the worker.
+pub struct Prefetcher {
+    wake: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl PrefetchConfig {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        Self {
+            depth: var("PREFETCH_DEPTH", 2),
+            concurrency: var("PREFETCH_CONCURRENCY", 2).max(1),
+            attempts: var("PREFETCH_RETRIES", 3),
+            ttl: Duration::from_secs(var("PREFETCH_TTL_SECS", 1200)),
+        }
+    }
+}
+
+impl Prefetcher {
+    pub fn spawn(
+        session: Weak<Mutex<GuildMusicSession>>,
+        sources: Arc<SourceRegistry>,
+        client: Client,
+    ) -> Self {
+        let wake = Arc::new(Notify::new());
+        let task = tokio::spawn(run(
+            session,
+            sources,
+            client,
+            wake.clone(),
+            PrefetchConfig::from_env(),
+        ));
+
+        Self { wake, task }
+    }
+
+    /// Asks the worker to look at the queue again, e.g. after it changed.
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Creates an input and opens it, which is the slow part of starting a track.
+#[tracing::instrument(name = "prefetch", skip_all, fields(url = %info.url))]
+pub async fn resolve(sources: &SourceRegistry, info: &TrackInfo, client: Client) -> Result<Input, Error> {
+    let input = sources.create_input(info, client).await?;
+    Ok(input.make_playable_async(&CODEC_REGISTRY, &PROBE).await?)
+}
+
+async fn run(
+    session: Weak<Mutex<GuildMusicSession>>,
+    sources: Arc<SourceRegistry>,
+    client: Client,
+    wake: Arc<Notify>,
+    config: PrefetchConfig,
+) {
+    let permits = Arc::new(Semaphore::new(config.concurrency));
+    let mut running: JoinSet<Result<Input, Error>> = JoinSet::new();
+    // Kept outside the tasks so one that panicked or was aborted still hands its track back
+    let mut tracks: HashMap<task::Id, u64> = HashMap::new();
+
+    loop {
+        // The session is only locked to pick jobs and to store results, never while resolving
+        let jobs = {
+            let Some(session) = session.upgrade() else {
+                return;
+            };
+            let mut session = session.lock().await;
+            session.voice_state.prefetch_jobs(&config)
+        };
+
+        for job in jobs {
+            let permits = permits.clone();
+            let sources = sources.clone();
+            let client = client.clone();
+
+            let task = running.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                resolve(&sources, &job.info, client).await
+            });
+            tracks.insert(task.id(), job.id);
+        }
+
+        tokio::select! {
+            _ = wake.notified() => {}
+            _ = tokio::time::sleep(REFRESH_INTERVAL) => {}
+            Some(done) = running.join_next_with_id(), if !running.is_empty() => {
+                let (task_id, result) = match done {
+                    Ok((task_id, result)) => (task_id, result),
+                    Err(err) => (err.id(), Err(anyhow::anyhow!("Resolve task failed: {}", err))),
+                };
+                let Some(id) = tracks.remove(&task_id) else {
+                    continue;
+                };
+                let Some(session) = session.upgrade() else {
+                    return;
+                };
+                let mut session = session.lock().await;
+                session.voice_state.store_prefetched(id, result, &config);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serenity::all::{ChannelId, GuildId, UserId};
+    use serenity::async_trait;
+
+    use super::*;
+    use crate::sources::MediaSource;
+
+    /// Fails every track after a short wait, recording how many were opened at once.
+    #[derive(Default)]
+    struct Counters {
+        calls: AtomicUsize,
+        running: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    struct SlowSource {
+        counters: Arc<Counters>,
+        panic: bool,
+    }
+
+    #[async_trait]
+    impl MediaSource for SlowSource {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn matches(&self, _query: &str) -> bool {
+            false
+        }
+
+        async fn resolve(&self, _query: &str) -> Result<Vec<TrackInfo>, Error> {
+            Ok(Vec::new())
+        }
+
+        async fn create_input(&self, _track: &TrackInfo, _client: Client) -> Result<Input, Error> {
+            let counters = &self.counters;
+            counters.calls.fetch_add(1, Ordering::SeqCst);
+            if self.panic {
+                panic!("source blew up");
+            }
+
+            let running = counters.running.fetch_add(1, Ordering::SeqCst) + 1;
+            counters.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            counters.running.fetch_sub(1, Ordering::SeqCst);
+
+            Err(anyhow::anyhow!("unavailable"))
+        }
+    }
+
+    fn session(tracks: usize) -> Arc<Mutex<GuildMusicSession>> {
+        let mut session =
+            GuildMusicSession::new(None, GuildId::new(1), ChannelId::new(1), ChannelId::new(1));
+        for i in 0..tracks {
+            let info = TrackInfo {
+                source: "test".to_string(),
+                url: format!("https://example.com/{}", i),
+                title: i.to_string(),
+                artist: "artist".to_string(),
+                duration_ms: None,
+                thumbnail: None,
+                playback_url: None,
+                is_live: false,
+            };
+            session.voice_state.add_track(info, UserId::new(1), None).unwrap();
+        }
+        Arc::new(Mutex::new(session))
+    }
+
+    fn spawn_worker(
+        session: &Arc<Mutex<GuildMusicSession>>,
+        source: SlowSource,
+        config: PrefetchConfig,
+    ) -> JoinHandle<()> {
+        let mut sources = SourceRegistry::new();
+        sources.register(source);
+
+        tokio::spawn(run(
+            Arc::downgrade(session),
+            Arc::new(sources),
+            Client::new(),
+            Arc::new(Notify::new()),
+            config,
+        ))
+    }
+
+    async fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out");
+    }
+
+    fn config(depth: usize, concurrency: usize) -> PrefetchConfig {
+        PrefetchConfig {
+            depth,
+            concurrency,
+            attempts: 3,
+            ttl: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_no_more_tracks_at_once_than_allowed() {
+        let session = session(4);
+        let counters = Arc::new(Counters::default());
+        let source = SlowSource {
+            counters: counters.clone(),
+            panic: false,
+        };
+        let worker = spawn_worker(&session, source, config(4, 2));
+
+        wait_for(|| counters.calls.load(Ordering::SeqCst) == 4).await;
+        worker.abort();
+
+        assert_eq!(counters.peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_panicking_resolve_still_releases_its_track() {
+        let session = session(1);
+        let counters = Arc::new(Counters::default());
+        let source = SlowSource {
+            counters: counters.clone(),
+            panic: true,
+        };
+        let worker = spawn_worker(&session, source, config(1, 1));
+
+        wait_for(|| {
+            session.try_lock().is_ok_and(|s| s.voice_state.queue[0].prefetch.failures == 1)
+        })
+        .await;
+        worker.abort();
+
+        let guard = session.lock().await;
+        let status = &guard.voice_state.queue[0].prefetch;
+        assert!(!status.in_flight);
+        assert!(status.retry_at.is_some());
+    }
+}
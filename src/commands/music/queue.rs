@@ -1,25 +1,46 @@
 use reqwest::Client;
-use serenity::all::{ChannelId, GuildId};
+use serenity::all::{ChannelId, Context, GuildId, UserId};
 use songbird::{
     Call,
-    input::{
-        AuxMetadata, Input, YoutubeDl,
-        codecs::{CODEC_REGISTRY, PROBE},
-    },
+    input::{AuxMetadata, Input},
     tracks::TrackHandle,
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    env,
+    fmt,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 use symphonia::core::{codecs::CODEC_TYPE_OPUS, probe::Probe};
 use tokio::sync::Mutex;
 
+use crate::bot::MusicStateKey;
+use crate::commands::music::history;
+use crate::commands::music::prefetch::{PrefetchConfig, PrefetchJob, Prefetcher};
+use crate::metrics;
+use crate::sources::{SourceRegistry, TrackInfo};
+use crate::store;
+use crate::store::settings::QueueLimits;
+
+/// Every guild's session sits behind its own lock so guilds never wait on each other,
+/// the map itself is only locked long enough to look a session up.
 pub struct BotMusicState {
-    pub music_sessions: HashMap<GuildId, GuildMusicSession>,
+    music_sessions: RwLock<HashMap<GuildId, SessionHandle>>,
 }
 
+pub type SessionHandle = Arc<Mutex<GuildMusicSession>>;
+
 pub struct GuildMusicSession {
     pub guild_id: GuildId,
     pub channel_id: ChannelId,
+    /// Text channel the session was started from, playback announcements go there.
+    pub text_channel: ChannelId,
     pub voice_state: VoiceChannelMusicState,
+    pub prefetch: Option<Prefetcher>,
 }
 
 pub struct VoiceChannelMusicState {
@@ -28,29 +49,233 @@ pub struct VoiceChannelMusicState {
     pub now_playing: Option<(TrackHandle, Option<AuxMetadata>)>,
     pub index_playing: usize,
     pub volume: f32,
+    pub limits: QueueLimits,
+    /// A track is being started, `now_playing` is set once it is.
+    pub starting: bool,
+}
+
+/// What came of trying to start the current track.
+pub enum Started {
+    Playing(TrackHandle),
+    /// Someone else started playback in the meantime.
+    AlreadyPlaying,
+    Unavailable,
 }
 
+/// Why a track wasn't let into the queue.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueRejection {
+    QueueFull(usize),
+    UserLimit(usize),
+    TooLong(u32),
+    Duplicate,
+}
+
+/// First wait before retrying a failed prefetch, doubled on every further failure up to
+/// the maximum.
+const PREFETCH_RETRY_DELAY: Duration = Duration::from_secs(5);
+const PREFETCH_MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+static NEXT_TRACK_ID: AtomicU64 = AtomicU64::new(0);
+
 pub struct QueuedTrack {
-    pub url: String,
+    /// Stays the same while the track moves around the queue.
+    pub id: u64,
+    pub info: TrackInfo,
+    pub requested_by: UserId,
+    /// Unix time the track last started playing.
+    pub started_at: Option<u64>,
+    /// Picked by autoplay rather than requested by someone.
+    pub autoplay: bool,
     pub resolved: Option<Input>,
+    pub prefetch: PrefetchStatus,
+    /// Times the track broke off or failed to open.
+    pub failures: u32,
+    /// Where to pick up again after the track broke off.
+    pub resume_from: Option<Duration>,
+    /// Start time of the history entry `pb!previous` brought the track back from, walking
+    /// back further continues before that entry.
+    pub rewound_to: Option<u64>,
+}
+
+/// Where the background worker is with resolving a track.
+#[derive(Debug, Default)]
+pub struct PrefetchStatus {
+    pub in_flight: bool,
+    pub failures: u32,
+    pub retry_at: Option<Instant>,
+    /// When `QueuedTrack::resolved` was opened, stream URLs stop working after a while.
+    pub resolved_at: Option<Instant>,
+}
+
+impl fmt::Display for QueueRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull(max) => write!(f, "The queue is full ({} tracks)", max),
+            Self::UserLimit(max) => write!(f, "You already have {} tracks waiting in the queue", max),
+            Self::TooLong(max) => write!(
+                f,
+                "Tracks longer than {} can't be queued",
+                crate::utils::serenity_utils::format_duration(max * 1000)
+            ),
+            Self::Duplicate => write!(f, "That track is already in the queue"),
+        }
+    }
 }
 
 impl BotMusicState {
     pub fn new() -> Self {
         Self {
-            music_sessions: HashMap::new(),
+            music_sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn session(&self, guild_id: GuildId) -> Option<SessionHandle> {
+        self.music_sessions.read().unwrap().get(&guild_id).cloned()
+    }
+
+    /// Adds `session` unless its guild already has one, returns the guild's session either way.
+    pub fn insert(&self, session: GuildMusicSession) -> SessionHandle {
+        self.music_sessions
+            .write()
+            .unwrap()
+            .entry(session.guild_id)
+            .or_insert_with(|| Arc::new(Mutex::new(session)))
+            .clone()
+    }
+
+    pub fn remove(&self, guild_id: GuildId) -> Option<SessionHandle> {
+        self.music_sessions.write().unwrap().remove(&guild_id)
+    }
+
+    pub fn sessions(&self) -> Vec<SessionHandle> {
+        self.music_sessions.read().unwrap().values().cloned().collect()
+    }
+}
+
+/// The music session of `guild_id`, if the bot is in voice there.
+pub async fn guild_session(ctx: &Context, guild_id: GuildId) -> Option<SessionHandle> {
+    let data = ctx.data.read().await;
+    data.get::<MusicStateKey>()?.session(guild_id)
+}
+
+/// Starts the track at `index_playing`. The session is only locked to take the track's
+/// input and to record the handle, resolving and opening the track happen without it.
+#[tracing::instrument(skip_all, fields(track_id = tracing::field::Empty))]
+pub async fn play_current(session: &SessionHandle, sources: &SourceRegistry, client: Client) -> Started {
+    let (call, id, info, input, volume, resume_from) = {
+        let mut guard = session.lock().await;
+        let state = &mut guard.voice_state;
+
+        if state.now_playing.is_some() || state.starting {
+            return Started::AlreadyPlaying;
+        }
+
+        let Some(call) = state.call.clone() else {
+            return Started::Unavailable;
+        };
+        let volume = state.volume;
+        let Some(track) = state.get_current_track() else {
+            return Started::Unavailable;
+        };
+        let (id, info, input) = (track.id, track.info.clone(), track.take_input());
+        tracing::Span::current().record("track_id", id);
+        let resume_from = track.resume_from.take().filter(|_| !info.is_live);
+
+        state.starting = true;
+        (call, id, info, input, volume, resume_from)
+    };
+
+    // Tracks the prefetcher hasn't got to are resolved only once their turn comes
+    let input = match input {
+        Some(input) => {
+            metrics::INPUT_CACHE.with_label_values(&["hit"]).inc();
+            Ok(input)
+        }
+        None => {
+            metrics::INPUT_CACHE.with_label_values(&["miss"]).inc();
+            sources.create_input(&info, client).await
+        }
+    };
+
+    let handle = match input {
+        Ok(mut input) => {
+            let metadata = input.aux_metadata().await.ok();
+            let handle = call.lock().await.play_input(input);
+            let _ = handle.set_volume(volume);
+            if let Some(position) = resume_from {
+                tracing::info!("Resuming {} at {:?}", info.url, position);
+                let _ = handle.seek(position);
+            }
+            Some((handle, metadata))
+        }
+        Err(err) => {
+            tracing::error!("Failed to create input for {}: {:?}", info.url, err);
+            None
+        }
+    };
+
+    let mut guard = session.lock().await;
+    let state = &mut guard.voice_state;
+    state.starting = false;
+
+    let Some((handle, metadata)) = handle else {
+        // Keep the resume position for the next attempt
+        if let Some(track) = state.get_current_track()
+            && track.id == id
+        {
+            track.resume_from = resume_from;
+        }
+        return Started::Unavailable;
+    };
+
+    match state.get_current_track() {
+        Some(track) if track.id == id => {
+            track.started_at = Some(store::unix_now());
+            state.now_playing = Some((handle.clone(), metadata));
+            metrics::TRACKS_PLAYED.inc();
+            Started::Playing(handle)
+        }
+        // The queue moved on while the track was being opened
+        _ => {
+            let _ = handle.stop();
+            Started::Unavailable
         }
     }
 }
 
 impl GuildMusicSession {
-    pub fn new(call: Option<Arc<Mutex<Call>>>, guild_id: GuildId, channel_id: ChannelId) -> Self {
+    pub fn new(
+        call: Option<Arc<Mutex<Call>>>,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        text_channel: ChannelId,
+    ) -> Self {
         Self {
             guild_id,
             channel_id,
+            text_channel,
             voice_state: VoiceChannelMusicState::new(call),
+            prefetch: None,
         }
     }
+
+    pub fn wake_prefetch(&self) {
+        if let Some(prefetch) = &self.prefetch {
+            prefetch.wake();
+        }
+    }
+
+    /// Takes the playing track off the session so its end moves nothing on, and records
+    /// how far it got in the history since `OnEnd` won't.
+    pub fn interrupt(&mut self) -> Option<TrackHandle> {
+        let (handle, _) = self.voice_state.now_playing.take()?;
+        if let Some(track) = self.voice_state.queue.get(self.voice_state.index_playing) {
+            history::record_play(self.guild_id, track, true);
+        }
+
+        Some(handle)
+    }
 }
 
 impl VoiceChannelMusicState {
@@ -61,80 +286,520 @@ impl VoiceChannelMusicState {
             now_playing: None,
             index_playing: 0,
             volume: 1.0,
+            limits: QueueLimits::default(),
+            starting: false,
         }
     }
 
-    pub async fn playing_track(&mut self) -> Option<TrackHandle> {
-        let call = &mut self.call.clone()?;
+    /// Nothing is playing or about to.
+    pub fn is_idle(&self) -> bool {
+        self.now_playing.is_none() && !self.starting
+    }
+
+    /// Handle and title of the track playing right now. The title comes from the queue,
+    /// HTTP and file inputs carry no metadata.
+    pub fn playing(&self) -> Option<(TrackHandle, String)> {
+        let (handle, metadata) = self.now_playing.as_ref()?;
+        let title = match self.queue.get(self.index_playing) {
+            Some(track) => track.info.title.clone(),
+            None => metadata
+                .as_ref()
+                .and_then(|m| m.title.clone())
+                .unwrap_or_else(|| "unknown".to_string()),
+        };
 
-        let mut handler = call.lock().await;
-        let current_track = self.get_current_track()?;
+        Some((handle.clone(), title))
+    }
 
-        if let Some(mut input) = current_track.take_input() {
-            let metadata: Option<AuxMetadata> = match input.aux_metadata().await {
-                Ok(meta) => Some(meta),
-                Err(_) => None,
-            };
+    /// Queues a track at `slot` (a queue index), or at the end when `None`.
+    /// Its input is left to the prefetcher. Returns its 1-based position in the queue.
+    pub fn add_track(
+        &mut self,
+        info: TrackInfo,
+        requested_by: UserId,
+        slot: Option<usize>,
+    ) -> Result<usize, QueueRejection> {
+        self.clear_upcoming_autoplay();
+        self.admit(&info, requested_by)?;
 
-            let handle = handler.play_input(input);
+        Ok(self.insert_requested(QueuedTrack::new(info, requested_by), slot))
+    }
 
-            let _ = handle.set_volume(self.volume);
-            self.now_playing = Some((handle.clone(), metadata));
+    /// Checks `info` against the guild's queue limits.
+    fn admit(&self, info: &TrackInfo, requested_by: UserId) -> Result<(), QueueRejection> {
+        let upcoming = self.upcoming();
 
-            Some(handle)
-        } else {
-            None
+        if let Some(max) = self.limits.max_queue_length
+            && upcoming.len() >= max
+        {
+            return Err(QueueRejection::QueueFull(max));
         }
+
+        if let Some(max) = self.limits.max_tracks_per_user
+            && upcoming.iter().filter(|q| q.requested_by == requested_by).count() >= max
+        {
+            return Err(QueueRejection::UserLimit(max));
+        }
+
+        // Streams have no end, the cap is about single long tracks
+        if let Some(max) = self.limits.max_track_duration
+            && !info.is_live
+            && info.duration_ms.is_some_and(|ms| ms / 1000 > max)
+        {
+            return Err(QueueRejection::TooLong(max));
+        }
+
+        if self.limits.no_duplicates && upcoming.iter().any(|q| q.info.url == info.url) {
+            return Err(QueueRejection::Duplicate);
+        }
+
+        Ok(())
     }
 
-    pub async fn add_track(&mut self, url: String, client: Client) -> usize {
-        let mut track = QueuedTrack {
-            url: url.clone(),
-            resolved: None,
+    /// Puts `track` at `slot`, never before what is playing. Without a slot it is appended,
+    /// or slotted into its requester's next turn in fair queue mode.
+    fn insert_requested(&mut self, track: QueuedTrack, slot: Option<usize>) -> usize {
+        let position = match slot {
+            Some(slot) => slot.clamp(self.first_upcoming(), self.queue.len()),
+            None if self.limits.fair_queue => self.fair_position(track.requested_by),
+            None => self.queue.len(),
         };
 
-        match self.now_playing {
-            Some(_) => track.preload(client, true).await,
-            None => track.preload(client, false).await,
-        };
+        self.queue.insert(position, track);
+        position + 1
+    }
+
+    /// Round-robin slot for a new track of `requested_by`: after every upcoming track
+    /// whose requester has had as many turns as `requested_by` already has.
+    fn fair_position(&self, requested_by: UserId) -> usize {
+        let first_upcoming = self.first_upcoming();
+        let upcoming = self.upcoming();
+
+        let round = upcoming
+            .iter()
+            .filter(|q| q.requested_by == requested_by)
+            .count();
 
+        let mut turns: HashMap<UserId, usize> = HashMap::new();
+        let mut position = first_upcoming;
+
+        for (i, track) in upcoming.iter().enumerate() {
+            let turn = turns.entry(track.requested_by).or_default();
+            if *turn <= round {
+                position = first_upcoming + i + 1;
+            }
+            *turn += 1;
+        }
+
+        position
+    }
+
+    /// Index of the first track that hasn't started playing yet.
+    pub fn first_upcoming(&self) -> usize {
+        let current = usize::from(self.now_playing.is_some());
+        (self.index_playing + current).min(self.queue.len())
+    }
+
+    pub fn upcoming(&self) -> &[QueuedTrack] {
+        &self.queue[self.first_upcoming()..]
+    }
+
+    /// Queues a track picked by autoplay, labelled as such.
+    pub fn add_autoplay_track(&mut self, info: TrackInfo, requested_by: UserId) {
+        let mut track = QueuedTrack::new(info, requested_by);
+        track.autoplay = true;
         self.queue.push(track);
+    }
+
+    /// Requested tracks take over from autoplay picks that haven't started yet.
+    fn clear_upcoming_autoplay(&mut self) {
+        let mut upcoming = self.queue.split_off(self.first_upcoming());
+        upcoming.retain(|track| !track.autoplay);
+        self.queue.extend(upcoming);
+    }
+
+    /// Estimated time until the track at queue `index` starts, `None` when a live stream
+    /// or a track of unknown length plays before it.
+    pub fn eta(&self, index: usize) -> impl Future<Output = Option<u64>> + Send + 'static {
+        let first_upcoming = self.first_upcoming();
+        let length = |track: &QueuedTrack| track.info.duration_ms.filter(|_| !track.info.is_live);
+
+        // Gathered up front, the queue itself can't be held across the position lookup
+        let current = self
+            .now_playing
+            .as_ref()
+            .map(|(handle, _)| (handle.clone(), self.queue.get(self.index_playing).and_then(length)));
+        let ahead: Option<u64> = self
+            .queue
+            .get(first_upcoming..index.max(first_upcoming))
+            .and_then(|tracks| tracks.iter().map(|t| length(t).map(u64::from)).sum());
+
+        async move {
+            if index < first_upcoming {
+                return Some(0);
+            }
+
+            let mut eta = ahead?;
+
+            if let Some((handle, duration)) = current {
+                let elapsed = handle.get_info().await.map(|i| i.position.as_millis()).unwrap_or(0);
+                eta += u64::from(duration?).saturating_sub(elapsed as u64);
+            }
+
+            Some(eta)
+        }
+    }
+
+    /// Counts a failure of the current track. Once it failed more than `TRACK_RETRIES`
+    /// (default 2) times the queue moves past it and its info is returned.
+    pub fn note_failure(&mut self) -> Option<TrackInfo> {
+        let retries = env::var("TRACK_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2);
 
-        self.queue.iter().position(|q| q.url == url).unwrap_or(0) + 1
+        let track = self.get_current_track()?;
+        track.failures += 1;
+
+        if track.failures <= retries {
+            return None;
+        }
+
+        let info = track.info.clone();
+        self.index_playing += 1;
+        Some(info)
+    }
+
+    /// Whether every queued track has been played.
+    pub fn is_exhausted(&self) -> bool {
+        self.index_playing >= self.queue.len()
+    }
+
+    /// Queues a track to play right after the current one, or first when nothing plays.
+    pub fn insert_next_lazy(&mut self, info: TrackInfo, requested_by: UserId) -> &mut QueuedTrack {
+        let position = self.first_upcoming();
+        self.queue.insert(position, QueuedTrack::new(info, requested_by));
+        &mut self.queue[position]
     }
 
     pub fn get_current_track(&mut self) -> Option<&mut QueuedTrack> {
         self.queue.get_mut(self.index_playing)
     }
+
+    /// Upcoming tracks within the prefetch depth that need resolving, marked in flight.
+    /// Inputs that outlived the TTL are dropped first so they get resolved again.
+    pub fn prefetch_jobs(&mut self, config: &PrefetchConfig) -> Vec<PrefetchJob> {
+        let now = Instant::now();
+        let first_upcoming = self.first_upcoming();
+        let mut jobs = Vec::new();
+
+        for track in self.queue.iter_mut().skip(first_upcoming).take(config.depth) {
+            // Opening a live stream early would only buffer audio nobody is listening to yet
+            if track.info.is_live || track.prefetch.in_flight {
+                continue;
+            }
+
+            if track.is_expired(config) {
+                tracing::debug!("Prefetched input of {} expired", track.info.url);
+                track.resolved = None;
+                track.prefetch.resolved_at = None;
+            }
+
+            let due = track.prefetch.retry_at.is_none_or(|at| at <= now);
+            if track.resolved.is_some() || track.prefetch.failures >= config.attempts || !due {
+                continue;
+            }
+
+            track.prefetch.in_flight = true;
+            jobs.push(PrefetchJob {
+                id: track.id,
+                info: track.info.clone(),
+            });
+        }
+
+        jobs
+    }
+
+    /// Hands a finished resolution back to its track, if it is still waiting for one.
+    pub fn store_prefetched(&mut self, id: u64, result: anyhow::Result<Input>, config: &PrefetchConfig) {
+        let Some(track) = self.queue.iter_mut().find(|t| t.id == id) else {
+            return;
+        };
+        track.prefetch.in_flight = false;
+
+        match result {
+            Ok(_) if track.started_at.is_some() => {}
+            Ok(input) => {
+                tracing::info!("Prefetched {}", track.info.url);
+                track.resolved = Some(input);
+                track.prefetch.resolved_at = Some(Instant::now());
+            }
+            Err(err) => {
+                track.prefetch.failures += 1;
+                tracing::warn!(
+                    "Prefetch of {} failed ({}/{}): {:?}",
+                    track.info.url,
+                    track.prefetch.failures,
+                    config.attempts,
+                    err
+                );
+
+                let factor = 2u32.saturating_pow(track.prefetch.failures.saturating_sub(1));
+                let backoff = PREFETCH_RETRY_DELAY
+                    .saturating_mul(factor)
+                    .min(PREFETCH_MAX_RETRY_DELAY);
+                track.prefetch.retry_at = Some(Instant::now() + backoff);
+            }
+        }
+    }
 }
 
 impl QueuedTrack {
-    pub async fn preload(&mut self, client: Client, is_preload: bool) {
-        if self.resolved.is_none() {
-            let input = Input::from(YoutubeDl::new(client, self.url.clone()).user_args(vec![
-                "--no-playlist".into(),
-                "-f".into(),
-                "bestaudio[acodec=opus]/bestaudio".into(),
-            ]));
-
-            match is_preload {
-                true => match input.make_playable_async(&CODEC_REGISTRY, &PROBE).await {
-                    Ok(i) => {
-                        tracing::info!("Preload song");
-                        self.resolved = Some(i)
-                    }
-                    Err(err) => {
-                        tracing::error!("Error : {}", err);
-                        self.resolved = None
-                    }
-                },
-                false => self.resolved = Some(input),
-            }
+    pub fn new(info: TrackInfo, requested_by: UserId) -> Self {
+        Self {
+            id: NEXT_TRACK_ID.fetch_add(1, Ordering::Relaxed),
+            info,
+            requested_by,
+            started_at: None,
+            autoplay: false,
+            resolved: None,
+            prefetch: PrefetchStatus::default(),
+            failures: 0,
+            resume_from: None,
+            rewound_to: None,
         }
     }
 
+    fn is_expired(&self, config: &PrefetchConfig) -> bool {
+        self.prefetch
+            .resolved_at
+            .is_some_and(|at| at.elapsed() >= config.ttl)
+    }
+
+    /// The prefetched input, unless it has expired.
     pub fn take_input(&mut self) -> Option<Input> {
         tracing::info!("Taking input");
-        self.resolved.take()
+        let expired = self.is_expired(&PrefetchConfig::from_env());
+        self.prefetch.resolved_at = None;
+        self.resolved.take().filter(|_| !expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(url: &str) -> TrackInfo {
+        TrackInfo {
+            source: "test".to_string(),
+            url: url.to_string(),
+            title: url.to_string(),
+            artist: "artist".to_string(),
+            duration_ms: Some(60_000),
+            thumbnail: None,
+            playback_url: None,
+            is_live: false,
+        }
+    }
+
+    fn config(attempts: u32, ttl: Duration) -> PrefetchConfig {
+        PrefetchConfig {
+            depth: 2,
+            concurrency: 1,
+            attempts,
+            ttl,
+        }
+    }
+
+    fn state_with(urls: &[&str]) -> VoiceChannelMusicState {
+        let mut state = VoiceChannelMusicState::new(None);
+        for url in urls {
+            state.add_track(track(url), UserId::new(1), None).unwrap();
+        }
+        state
+    }
+
+    fn input() -> Input {
+        Input::from(Vec::<u8>::new())
+    }
+
+    #[tokio::test]
+    async fn playing_track_without_metadata_is_named_from_the_queue() {
+        let mut state = state_with(&["a"]);
+        let mut driver = songbird::Driver::default();
+        state.now_playing = Some((driver.play_input(input()), None));
+
+        let (_, title) = state.playing().unwrap();
+
+        assert_eq!(title, "a");
+    }
+
+    #[test]
+    fn prefetches_only_within_depth_and_skips_live_tracks() {
+        let mut state = state_with(&["a", "b", "c"]);
+        state.queue[1].info.is_live = true;
+
+        let jobs = state.prefetch_jobs(&config(3, Duration::from_secs(60)));
+        let ids = jobs.iter().map(|j| j.info.url.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(ids, ["a"]);
+    }
+
+    #[test]
+    fn tracks_in_flight_are_not_handed_out_twice() {
+        let mut state = state_with(&["a"]);
+        let config = config(3, Duration::from_secs(60));
+
+        assert_eq!(state.prefetch_jobs(&config).len(), 1);
+        assert!(state.prefetch_jobs(&config).is_empty());
+    }
+
+    #[test]
+    fn failed_prefetch_is_retried_after_backoff_until_attempts_run_out() {
+        let mut state = state_with(&["a"]);
+        let config = config(2, Duration::from_secs(60));
+        let id = state.queue[0].id;
+
+        state.prefetch_jobs(&config);
+        state.store_prefetched(id, Err(anyhow::anyhow!("down")), &config);
+        assert!(!state.queue[0].prefetch.in_flight);
+        assert!(state.prefetch_jobs(&config).is_empty(), "retried before the backoff");
+
+        state.queue[0].prefetch.retry_at = Some(Instant::now());
+        assert_eq!(state.prefetch_jobs(&config).len(), 1);
+        state.store_prefetched(id, Err(anyhow::anyhow!("down")), &config);
+
+        state.queue[0].prefetch.retry_at = Some(Instant::now());
+        assert!(state.prefetch_jobs(&config).is_empty(), "retried past the attempt limit");
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let mut state = state_with(&["a"]);
+        let config = config(u32::MAX, Duration::from_secs(60));
+        let id = state.queue[0].id;
+        state.queue[0].prefetch.failures = u32::MAX - 1;
+
+        state.store_prefetched(id, Err(anyhow::anyhow!("down")), &config);
+
+        let retry_at = state.queue[0].prefetch.retry_at.unwrap();
+        assert!(retry_at <= Instant::now() + PREFETCH_MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn expired_inputs_are_resolved_again() {
+        let mut state = state_with(&["a"]);
+        let id = state.queue[0].id;
+
+        let fresh = config(3, Duration::from_secs(60));
+        state.prefetch_jobs(&fresh);
+        state.store_prefetched(id, Ok(input()), &fresh);
+        assert!(state.queue[0].resolved.is_some());
+        assert!(state.prefetch_jobs(&fresh).is_empty());
+
+        let expired = config(3, Duration::ZERO);
+        assert_eq!(state.prefetch_jobs(&expired).len(), 1);
+        assert!(state.queue[0].resolved.is_none());
+    }
+
+    #[test]
+    fn inputs_of_tracks_that_already_started_are_dropped() {
+        let mut state = state_with(&["a"]);
+        let config = config(3, Duration::from_secs(60));
+        let id = state.queue[0].id;
+
+        state.prefetch_jobs(&config);
+        state.queue[0].started_at = Some(store::unix_now());
+        state.store_prefetched(id, Ok(input()), &config);
+
+        assert!(state.queue[0].resolved.is_none());
+    }
+
+    fn fair_state() -> VoiceChannelMusicState {
+        let mut state = VoiceChannelMusicState::new(None);
+        state.limits.fair_queue = true;
+        state
+    }
+
+    /// Queues `url` for the member `user`, unwrapping the admission.
+    fn request(state: &mut VoiceChannelMusicState, user: u64, url: &str) {
+        state.add_track(track(url), UserId::new(user), None).unwrap();
+    }
+
+    fn queued(state: &VoiceChannelMusicState) -> Vec<&str> {
+        state.queue.iter().map(|q| q.info.url.as_str()).collect()
+    }
+
+    #[test]
+    fn members_are_held_to_their_track_limit() {
+        let mut state = VoiceChannelMusicState::new(None);
+        state.limits.max_tracks_per_user = Some(2);
+        request(&mut state, 1, "a1");
+        request(&mut state, 1, "a2");
+
+        let rejected = state.add_track(track("a3"), UserId::new(1), None);
+
+        assert_eq!(rejected, Err(QueueRejection::UserLimit(2)));
+        assert!(state.add_track(track("b1"), UserId::new(2), None).is_ok());
+    }
+
+    #[test]
+    fn tracks_over_the_duration_cap_are_refused() {
+        let mut state = VoiceChannelMusicState::new(None);
+        state.limits.max_track_duration = Some(59);
+
+        let rejected = state.add_track(track("a"), UserId::new(1), None);
+
+        assert_eq!(rejected, Err(QueueRejection::TooLong(59)));
+        assert!(state.queue.is_empty());
+    }
+
+    #[test]
+    fn live_tracks_bypass_the_duration_cap() {
+        let mut state = VoiceChannelMusicState::new(None);
+        state.limits.max_track_duration = Some(59);
+        let mut live = track("radio");
+        live.is_live = true;
+
+        assert_eq!(state.add_track(live, UserId::new(1), None), Ok(1));
+    }
+
+    #[test]
+    fn fair_queue_takes_turns_between_requesters() {
+        let mut state = fair_state();
+        request(&mut state, 1, "a1");
+        request(&mut state, 1, "a2");
+        request(&mut state, 1, "a3");
+        request(&mut state, 2, "b1");
+        request(&mut state, 3, "c1");
+        request(&mut state, 2, "b2");
+
+        assert_eq!(queued(&state), ["a1", "b1", "c1", "a2", "b2", "a3"]);
+    }
+
+    #[test]
+    fn fair_queue_puts_a_requester_with_queued_tracks_behind_the_others() {
+        let mut state = fair_state();
+        request(&mut state, 1, "a1");
+        request(&mut state, 2, "b1");
+        request(&mut state, 1, "a2");
+        request(&mut state, 1, "a3");
+        request(&mut state, 2, "b2");
+
+        assert_eq!(queued(&state), ["a1", "b1", "a2", "b2", "a3"]);
+    }
+
+    #[tokio::test]
+    async fn fair_queue_gives_the_playing_requester_the_next_free_turn() {
+        let mut state = fair_state();
+        request(&mut state, 1, "a1");
+        request(&mut state, 2, "b1");
+        request(&mut state, 2, "b2");
+        let mut driver = songbird::Driver::default();
+        state.now_playing = Some((driver.play_input(input()), None));
+
+        request(&mut state, 1, "a2");
+
+        assert_eq!(queued(&state), ["a1", "b1", "a2", "b2"]);
     }
 }
@@ -1,197 +1,311 @@
-use std::sync::Arc;
-
 use serenity::{client::Context, model::prelude::Message};
-use songbird::{Event, TrackEvent};
 
 use crate::{
-    api::{spotify::get_track_by_id, youtube::search_youtube},
-    bot::{HttpKey, MusicStateKey},
+    bot::{HttpKey, SourceRegistryKey},
     commands::{
         self,
-        music::event::OnEnd,
+        music::{
+            event, favorites, queue,
+        },
     },
-    models::spotify::SpotifyTrackItem,
-    token::registry::TokenRegistry,
+    sources::{SourceRegistry, TrackInfo, attachment},
+    store::settings,
     utils::serenity_utils,
 };
 
-#[derive(Debug)]
-pub enum MediaTrack {
-    Spotify(SpotifyTrackItem),
-    // Future: YouTube(YouTubeTrackItem), SoundCloud(...), etc.
+const QUEUE_PAGE_SIZE: usize = 10;
+
+/// Where newly requested tracks go in the queue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    End,
+    /// Right after the current track.
+    Next,
+    /// Right after the current track, which is then skipped.
+    Now,
 }
 
-pub async fn run(ctx: Context, msg: Message, registry: TokenRegistry) {
-    if let Some(guild_id) = msg.guild_id {
-        let args = msg.content.strip_prefix("pb!play").unwrap_or("").trim();
-        tracing::debug!("Args: {:?}", args);
+pub async fn run(ctx: Context, msg: Message) {
+    play(ctx, msg, "pb!play", Placement::End).await;
+}
 
-        let media_result: Option<MediaTrack> = match parse_media(args, registry).await {
-            Ok(Some(media)) => {
-                tracing::debug!("Media track loaded: {:?}", media);
-                Some(media)
-            }
-            Ok(None) => {
+pub async fn play_next(ctx: Context, msg: Message) {
+    play(ctx, msg, "pb!playnext", Placement::Next).await;
+}
+
+pub async fn play_now(ctx: Context, msg: Message) {
+    play(ctx, msg, "pb!playnow", Placement::Now).await;
+}
+
+async fn play(ctx: Context, msg: Message, prefix: &str, placement: Placement) {
+    let args = msg.content.strip_prefix(prefix).unwrap_or("").trim().to_string();
+    tracing::debug!("Args: {:?}", args);
+
+    if args.is_empty() {
+        play_attachments(ctx, msg, placement).await;
+        return;
+    }
+
+    let words = args.split_whitespace().collect::<Vec<_>>();
+    if let ["favorites", flags @ ..] = words.as_slice()
+        && matches!(flags, [] | ["--server" | "-s"])
+    {
+        favorites::play(ctx, msg, !flags.is_empty()).await;
+        return;
+    }
+
+    play_query_at(ctx, msg, &args, placement).await;
+}
+
+/// Plays audio attached to the command itself, or to the message it replies to.
+async fn play_attachments(ctx: Context, msg: Message, placement: Placement) {
+    let attachments = if !msg.attachments.is_empty() {
+        msg.attachments.clone()
+    } else {
+        msg.referenced_message
+            .as_ref()
+            .map(|m| m.attachments.clone())
+            .unwrap_or_default()
+    };
+
+    if attachments.is_empty() {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            "Usage: pb!play <link or search>, or attach an audio file",
+            0x6C757D,
+        )
+        .await;
+        return;
+    }
+
+    let sources = {
+        let data = ctx.data.read().await;
+        data.get::<SourceRegistryKey>()
+            .cloned()
+            .expect("Guaranteed to exist in the typemap.")
+    };
+
+    let mut tracks = Vec::new();
+
+    for file in attachments {
+        if let Some(reason) = attachment::validate(&file) {
+            let _ = serenity_utils::send_embed(&ctx, &msg, &reason, 0xFF0000).await;
+            continue;
+        }
+
+        match sources.resolve(&file.url).await {
+            Ok(resolved) if !resolved.is_empty() => tracks.extend(resolved),
+            Ok(_) => {
                 let _ = serenity_utils::send_embed(
                     &ctx,
                     &msg,
-                    "Could not find the provided song",
+                    &format!("{} could not be read as audio", file.filename),
                     0xFF0000,
                 )
                 .await;
-                None
             }
             Err(e) => {
-                tracing::error!("Error while fetching media: {:?}", e);
-                let _ = serenity_utils::send_embed(&ctx, &msg, "Failed to load track 😞", 0xFF0000)
-                    .await;
-                None
+                tracing::error!("Error while fetching attachment {}: {:?}", file.filename, e);
+                let _ = serenity_utils::send_embed(
+                    &ctx,
+                    &msg,
+                    &format!("Failed to load {} 😞", file.filename),
+                    0xFF0000,
+                )
+                .await;
             }
+        }
+    }
+
+    if !tracks.is_empty() {
+        enqueue_at(ctx, msg, tracks, placement).await;
+    }
+}
+
+/// Resolves `query` through the source registry and queues whatever it finds.
+pub async fn play_query(ctx: Context, msg: Message, query: &str) {
+    play_query_at(ctx, msg, query, Placement::End).await;
+}
+
+async fn play_query_at(ctx: Context, msg: Message, query: &str, placement: Placement) {
+    let sources = {
+        let data = ctx.data.read().await;
+        data.get::<SourceRegistryKey>()
+            .cloned()
+            .expect("Guaranteed to exist in the typemap.")
+    };
+
+    let tracks: Vec<TrackInfo> = match parse_media(query, &sources).await {
+        Ok(tracks) if !tracks.is_empty() => {
+            tracing::debug!("Media track loaded: {:?}", tracks);
+            tracks
+        }
+        Ok(_) => {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                "Could not find the provided song",
+                0xFF0000,
+            )
+            .await;
+            return;
+        }
+        Err(e) => {
+            tracing::error!("Error while fetching media: {:?}", e);
+            let _ = serenity_utils::send_embed(&ctx, &msg, "Failed to load track 😞", 0xFF0000)
+                .await;
+            return;
+        }
+    };
+
+    enqueue_at(ctx, msg, tracks, placement).await;
+}
+
+/// Queues resolved tracks at the end of the queue.
+pub async fn enqueue(ctx: Context, msg: Message, tracks: Vec<TrackInfo>) {
+    enqueue_at(ctx, msg, tracks, Placement::End).await;
+}
+
+/// Queues resolved tracks, joining the author's voice channel and starting playback if idle.
+pub async fn enqueue_at(ctx: Context, msg: Message, tracks: Vec<TrackInfo>, placement: Placement) {
+    if let Some(guild_id) = msg.guild_id {
+        let (http_client, sources) = {
+            let data = ctx.data.read().await;
+            (
+                data.get::<HttpKey>()
+                    .cloned()
+                    .expect("Guaranteed to exist in the typemap."),
+                data.get::<SourceRegistryKey>()
+                    .cloned()
+                    .expect("Guaranteed to exist in the typemap."),
+            )
         };
 
-        match media_result {
-            Some(MediaTrack::Spotify(track_item)) => {
-                let http_client = {
-                    let data = ctx.data.read().await;
-                    data.get::<HttpKey>()
-                        .cloned()
-                        .expect("Guaranteed to exist in the typemap.")
-                };
+        let limits = settings::load(guild_id).await.limits;
 
-                let query = format!(
-                    "{} {}",
-                    track_item.name,
-                    track_item
-                        .artists
-                        .get(0)
-                        .map(|artist| artist.name.clone())
-                        .unwrap_or("".to_string())
-                );
-
-                let url = match search_youtube(&query).await {
-                    Ok(results) => {
-                        let video_id = results
-                            .into_iter()
-                            .next()
-                            .map(|res| res.video_id)
-                            .unwrap_or_default();
-                        let url = format!("https://www.youtube.com/watch?v={}", video_id);
-                        url
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to fetch YouTube source: {:?}", e);
-                        let _ = serenity_utils::send_embed(
-                            &ctx,
-                            &msg,
-                            "Failed to get audio source 😞",
-                            0xFF0000,
-                        )
-                        .await;
-                        return;
-                    }
-                };
+        // Force bot to join channel
+        commands::voice::join(ctx.clone(), msg.clone()).await;
 
-                // Force bot to join channel
-                commands::voice::join(ctx.clone(), msg.clone()).await;
-
-                let music_state = ctx
-                    .data
-                    .read()
-                    .await
-                    .get::<MusicStateKey>()
-                    .unwrap()
-                    .clone();
-                let mut state = music_state.lock().await;
-
-                if let Some(channel_state) = &mut state
-                    .music_sessions
-                    .get_mut(&guild_id)
-                    .and_then(|session| Some(&mut session.voice_state))
-                {
-                    let index = channel_state.add_track(url, http_client.clone()).await;
-
-                    let _ = serenity_utils::send_track_embed(
-                        &ctx,
-                        &msg,
-                        &track_item.name,
-                        &track_item
-                            .artists
-                            .get(0)
-                            .map(|a| a.name.to_owned())
-                            .unwrap_or("unknown".to_owned()),
-                        &track_item.duration_ms,
-                        &track_item
-                            .album
-                            .images
-                            .get(0)
-                            .map(|a| a.url.clone())
-                            .unwrap_or("".to_owned()),
-                        &(channel_state.index_playing + 1).to_string(),
-                        &index.to_string(),
-                        msg.author.clone(),
-                    )
-                    .await;
+        let session = match queue::guild_session(&ctx, guild_id).await {
+            Some(s) => s,
+            None => return,
+        };
 
-                    if let Some(call) = channel_state.call.clone() {
-                        if channel_state.now_playing.is_none() {
-                            let track_handle = channel_state.playing_track().await;
-
-                            match track_handle {
-                                Some(handle) => {
-                                    let shared_state = Arc::downgrade(&music_state);
-                                    let call_shared = Arc::downgrade(&call);
-
-                                    let _ = handle.add_event(
-                                        Event::Track(TrackEvent::End),
-                                        OnEnd {
-                                            ctx: ctx.clone(),
-                                            msg: msg.clone(),
-                                            guild_id: guild_id.clone(),
-                                            call: call_shared,
-                                            shared_state: shared_state.clone(),
-                                        },
-                                    );
-                                }
-                                None => {
-                                    let _ = serenity_utils::send_embed(
-                                        &ctx,
-                                        &msg,
-                                        "Failed to playing audio😞",
-                                        0xFF0000,
-                                    )
-                                    .await;
-                                }
-                            }
-                        };
+        let total = tracks.len();
+        let mut added = 0;
+        let mut rejection = None;
+        let mut card = None;
+
+        // Everything that awaits Discord or the driver happens after the session is released
+        let (stop_current, start, eta) = {
+            let mut guard = session.lock().await;
+            let channel_state = &mut guard.voice_state;
+            channel_state.limits = limits;
+
+            // Tracks of a set played next keep their order, each one goes after the previous
+            let mut slot = match placement {
+                Placement::End => None,
+                Placement::Next | Placement::Now => Some(channel_state.first_upcoming()),
+            };
+
+            let mut eta = None;
+
+            for track in tracks {
+                let position = match channel_state.add_track(track.clone(), msg.author.id, slot) {
+                    Ok(position) => position,
+                    Err(reason) => {
+                        rejection.get_or_insert(reason);
+                        continue;
+                    }
+                };
+                slot = slot.map(|_| position);
+
+                // Sets and albums only get a card for their first track
+                if added == 0 {
+                    let heading = match placement {
+                        Placement::Now if channel_state.now_playing.is_some() => "Playing Now",
+                        Placement::Next if channel_state.now_playing.is_some() => "Playing Next",
+                        _ => "Added Track Queue",
+                    };
+                    if placement != Placement::Now {
+                        eta = Some(channel_state.eta(position - 1));
                     }
+                    card = Some((track, heading, channel_state.index_playing + 1, position));
                 }
+                added += 1;
             }
-            None => return,
+
+            // Skipping the current track makes the queue move on to what was just inserted
+            let stop_current = match &channel_state.now_playing {
+                Some((handle, _)) if placement == Placement::Now && added > 0 => Some(handle.clone()),
+                _ => None,
+            };
+            let start = channel_state.is_idle() && !channel_state.is_exhausted();
+
+            (stop_current, start, eta)
+        };
+
+        if let Some((track, heading, current, position)) = card {
+            let eta = match eta {
+                Some(eta) => eta.await,
+                None => Some(0),
+            };
+
+            let _ = serenity_utils::send_track_embed(
+                &ctx, &msg, &track, heading, current, position, eta,
+            )
+            .await;
+        }
+
+        if added > 1 {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &format!("➕ Added {} more tracks from this set", added - 1),
+                0x00AAFF,
+            )
+            .await;
         }
+
+        if let Some(reason) = rejection {
+            let description = match total - added {
+                1 => reason.to_string(),
+                skipped => format!("{}, {} tracks were not added", reason, skipped),
+            };
+            let _ = serenity_utils::send_embed(&ctx, &msg, &description, 0xFF0000).await;
+        }
+
+        if let Some(handle) = stop_current {
+            let _ = handle.stop();
+        }
+
+        if start {
+            event::start_playback(&ctx, guild_id, &session, &sources, http_client).await;
+        }
+
+        session.lock().await.wake_prefetch();
     }
 }
 
 pub async fn pause(ctx: Context, msg: Message) {
     if let Some(guild_id) = msg.guild_id {
-        let music_state = ctx
-            .data
-            .read()
-            .await
-            .get::<MusicStateKey>()
-            .unwrap()
-            .clone();
-
-        let mut state = music_state.lock().await;
-        let session = match state.music_sessions.get_mut(&guild_id) {
+        let session = match queue::guild_session(&ctx, guild_id).await {
             Some(s) => s,
             None => {
                 return;
             }
         };
 
-        let channel_state = &mut session.voice_state;
+        let now_playing = session
+            .lock()
+            .await
+            .voice_state
+            .now_playing
+            .as_ref()
+            .map(|(handle, _)| handle.clone());
 
-        if let Some((track_handle, _)) = &mut channel_state.now_playing {
+        if let Some(track_handle) = now_playing {
             let _ = track_handle.pause();
 
             let _ = serenity_utils::send_embed(
@@ -211,25 +325,22 @@ pub async fn pause(ctx: Context, msg: Message) {
 
 pub async fn resume(ctx: Context, msg: Message) {
     if let Some(guild_id) = msg.guild_id {
-        let music_state = ctx
-            .data
-            .read()
-            .await
-            .get::<MusicStateKey>()
-            .unwrap()
-            .clone();
-
-        let mut state = music_state.lock().await;
-        let session = match state.music_sessions.get_mut(&guild_id) {
+        let session = match queue::guild_session(&ctx, guild_id).await {
             Some(s) => s,
             None => {
                 return;
             }
         };
 
-        let channel_state = &mut session.voice_state;
+        let now_playing = session
+            .lock()
+            .await
+            .voice_state
+            .now_playing
+            .as_ref()
+            .map(|(handle, _)| handle.clone());
 
-        if let Some((track_handle, _)) = &mut channel_state.now_playing {
+        if let Some(track_handle) = now_playing {
             let _ = track_handle.play();
 
             let _ =
@@ -248,30 +359,17 @@ pub async fn skip(ctx: Context, msg: Message) {
     let user_info = user_id.to_user(&ctx.http).await;
 
     if let Some(guild_id) = msg.guild_id {
-        let music_state = ctx
-            .data
-            .read()
-            .await
-            .get::<MusicStateKey>()
-            .unwrap()
-            .clone();
-
-        let mut state = music_state.lock().await;
-        let session = match state.music_sessions.get_mut(&guild_id) {
+        let session = match queue::guild_session(&ctx, guild_id).await {
             Some(s) => s,
             None => {
                 return;
             }
         };
 
-        let channel_state = &mut session.voice_state;
+        let playing = session.lock().await.voice_state.playing();
 
-        if let Some((track_handle, metadata)) = &channel_state.now_playing {
+        if let Some((track_handle, title)) = playing {
             let username = user_info.map(|u| u.name).unwrap_or("unknown".to_string());
-            let title = metadata
-                .clone()
-                .map(|m| m.title.unwrap_or("unknown".to_string()))
-                .unwrap();
 
             let _ = track_handle.stop();
 
@@ -294,6 +392,48 @@ pub async fn skip(ctx: Context, msg: Message) {
     }
 }
 
+/// Stops playback and clears the queue, but stays in the voice channel.
+pub async fn stop(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let session = match queue::guild_session(&ctx, guild_id).await {
+        Some(s) => s,
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "You're not playing any music", 0x6C757D)
+                .await;
+            return;
+        }
+    };
+
+    let (handle, call, cleared) = {
+        let mut guard = session.lock().await;
+        let handle = guard.interrupt();
+        let channel = &mut guard.voice_state;
+        let cleared = channel.queue.len();
+        channel.queue.clear();
+        channel.index_playing = 0;
+        (handle, channel.call.clone(), cleared)
+    };
+
+    if let Some(handle) = handle {
+        let _ = handle.stop();
+    }
+    if let Some(call) = call {
+        call.lock().await.stop();
+    }
+
+    let _ = serenity_utils::send_embed(
+        &ctx,
+        &msg,
+        &format!("⏹️ Stopped playback and cleared {} tracks from the queue", cleared),
+        0x6C757D,
+    )
+    .await;
+}
+
 pub async fn volume(ctx: Context, msg: Message) {
     let args = msg.content.strip_prefix("pb!volume").unwrap_or("").trim();
     let volume_request = args
@@ -308,22 +448,13 @@ pub async fn volume(ctx: Context, msg: Message) {
     };
 
     if let Some(guild_id) = msg.guild_id {
-        let music_state = ctx
-            .data
-            .read()
-            .await
-            .get::<MusicStateKey>()
-            .unwrap()
-            .clone();
-
-        let mut state = music_state.lock().await;
-
-        let session = match state.music_sessions.get_mut(&guild_id) {
+        let session = match queue::guild_session(&ctx, guild_id).await {
             Some(s) => s,
             None => return,
         };
 
-        let channel_state = &mut session.voice_state;
+        let mut guard = session.lock().await;
+        let channel_state = &mut guard.voice_state;
 
         channel_state.volume = new_volume;
 
@@ -333,24 +464,74 @@ pub async fn volume(ctx: Context, msg: Message) {
     }
 }
 
-async fn parse_media(args: &str, registry: TokenRegistry) -> anyhow::Result<Option<MediaTrack>> {
-    if args.contains("open.spotify.com/track/") {
-        if let Some(track_id) = extract_track_id(args) {
-            let track = get_track_by_id(track_id, registry).await?;
-            return Ok(Some(MediaTrack::Spotify(track)));
-        } else {
-            tracing::warn!("Could not extract Spotify ID");
-            return Ok(None);
+/// Lists what is playing and what comes next.
+pub async fn queue(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let session = match queue::guild_session(&ctx, guild_id).await {
+        Some(s) => s,
+        None => return,
+    };
+
+    let listing = {
+        let guard = session.lock().await;
+        let channel_state = &guard.voice_state;
+
+        let upcoming = channel_state
+            .queue
+            .iter()
+            .enumerate()
+            .skip(channel_state.index_playing)
+            .collect::<Vec<_>>();
+
+        let args = msg.content.strip_prefix("pb!queue").unwrap_or("").trim();
+        let pages = upcoming.len().div_ceil(QUEUE_PAGE_SIZE).max(1);
+        let page = args.parse::<usize>().unwrap_or(1).clamp(1, pages);
+
+        let lines = upcoming
+            .into_iter()
+            .skip((page - 1) * QUEUE_PAGE_SIZE)
+            .take(QUEUE_PAGE_SIZE)
+            .map(|(i, track)| {
+                let marker = if i == channel_state.index_playing {
+                    "▶️ ".to_string()
+                } else {
+                    format!("`{}.` ", i + 1)
+                };
+                let label = if track.autoplay {
+                    " · 📻 autoplay".to_string()
+                } else {
+                    format!(" · <@{}>", track.requested_by)
+                };
+
+                format!("{}{} — {}{}", marker, track.info.title, track.info.artist, label)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        (!lines.is_empty()).then_some((lines, pages, page))
+    };
+
+    let (lines, pages, page) = match listing {
+        Some(listing) => listing,
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "The queue is empty", 0x6C757D).await;
+            return;
         }
-    }
+    };
 
-    tracing::warn!("Unsupported URL or command: {}", args);
-    Ok(None)
+    let _ = serenity_utils::send_embed(
+        &ctx,
+        &msg,
+        &format!("**Queue**\n\n{}\n\nPage {}/{}", lines, page, pages),
+        0x00AAFF,
+    )
+    .await;
 }
 
-pub fn extract_track_id(url: &str) -> Option<String> {
-    url.split("/track/")
-        .nth(1)
-        .and_then(|s| s.split('?').next())
-        .map(|s| s.to_string())
+async fn parse_media(args: &str, sources: &SourceRegistry) -> anyhow::Result<Vec<TrackInfo>> {
+    sources.resolve(args).await
 }
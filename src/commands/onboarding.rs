@@ -0,0 +1,421 @@
+use std::time::Duration;
+
+use anyhow::{Error, Result, anyhow};
+use serenity::all::{
+    ButtonStyle, ChannelId, ChannelType, ComponentInteractionDataKind, CreateActionRow,
+    CreateButton, CreateChannel, CreateEmbed, CreateInteractionResponse, CreateMessage,
+    CreateSelectMenu, CreateSelectMenuKind, EditMessage, GuildChannel, GuildId, PermissionOverwrite,
+    PermissionOverwriteType, Permissions, RoleId,
+};
+use serenity::{client::Context, model::prelude::Message};
+
+use crate::store::settings::{self, AccessSettings};
+use crate::utils::serenity_utils;
+
+const CATEGORY_NAME: &str = "Bot Channels";
+const CHANNEL_NAME: &str = "kumar-channel";
+/// How long the wizard waits for the next pick before giving up.
+const WIZARD_TIMEOUT: Duration = Duration::from_secs(120);
+
+const CHANNEL_SELECT: &str = "setup-channel";
+const ROLE_SELECT: &str = "setup-role";
+const RESTRICT_BUTTON: &str = "setup-restrict";
+const VOICE_TEXT_BUTTON: &str = "setup-voice-text";
+const SAVE_BUTTON: &str = "setup-save";
+const CANCEL_BUTTON: &str = "setup-cancel";
+
+/// Most command channels the wizard lets an admin pick.
+const MAX_CHANNELS: u8 = 5;
+
+/// What the bot needs in its command channel to read commands and answer them.
+const CHANNEL_PERMISSIONS: Permissions = Permissions::VIEW_CHANNEL
+    .union(Permissions::SEND_MESSAGES)
+    .union(Permissions::EMBED_LINKS)
+    .union(Permissions::READ_MESSAGE_HISTORY);
+
+/// Makes sure `guild_id` has a command channel on record, reusing `#kumar-channel` or
+/// creating it when missing. Safe to run any number of times.
+pub async fn ensure(ctx: &Context, guild_id: GuildId) -> Result<ChannelId, Error> {
+    let access = settings::load(guild_id).await.access;
+
+    if let Some(channel_id) = access.channel_id
+        && find_channel(ctx, guild_id, |c| c.id == channel_id)?.is_some()
+    {
+        return Ok(channel_id);
+    }
+
+    let channel_id = match find_channel(ctx, guild_id, |c| {
+        c.kind == ChannelType::Text && c.name == CHANNEL_NAME
+    })? {
+        Some(id) => id,
+        None => create_channel(ctx, guild_id, access.role_id.filter(|_| access.restrict)).await?,
+    };
+
+    settings::update(guild_id, |s| s.access.channel_id = Some(channel_id)).await?;
+    tracing::info!("Onboarded guild {} with channel {}", guild_id, channel_id);
+
+    Ok(channel_id)
+}
+
+/// The channel commands are taken in: the recorded one, or `#kumar-channel` from the
+/// cache for guilds that were never set up. Never touches the API.
+pub fn command_channel(ctx: &Context, guild_id: GuildId, access: &AccessSettings) -> Option<ChannelId> {
+    access.channel_id.or_else(|| {
+        find_channel(ctx, guild_id, |c| c.kind == ChannelType::Text && c.name == CHANNEL_NAME)
+            .ok()
+            .flatten()
+    })
+}
+
+/// Forgets a command channel once it is deleted, the main one until `pb!setup` picks another.
+pub async fn on_channel_delete(channel: &GuildChannel) {
+    let access = settings::load(channel.guild_id).await.access;
+    if access.channel_id != Some(channel.id) && !access.extra_channels.contains(&channel.id) {
+        return;
+    }
+
+    let forgotten = settings::update(channel.guild_id, |s| {
+        s.access.extra_channels.retain(|c| *c != channel.id);
+        (s.access.channel_id == Some(channel.id)).then(|| s.access.channel_id = None)
+    })
+    .await;
+
+    match forgotten {
+        Ok(Some(())) => tracing::warn!(
+            "Command channel {} of guild {} was deleted, run pb!setup to pick another",
+            channel.id,
+            channel.guild_id
+        ),
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to forget channel {}: {:?}", channel.id, e),
+    }
+}
+
+/// Forgets a command channel if it stopped being a text channel.
+pub async fn on_channel_update(channel: &GuildChannel) {
+    if channel.kind == ChannelType::Text {
+        return;
+    }
+    on_channel_delete(channel).await;
+}
+
+/// Walks an admin through picking the command channels, the role allowed to use the bot
+/// and whether to restrict it at all, then saves the choices to the guild settings.
+pub async fn setup(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    if !serenity_utils::can_manage_guild(&ctx, &msg) {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            "Only members who can manage the server can run the setup",
+            0xFF0000,
+        )
+        .await;
+        return;
+    }
+
+    let mut choice = settings::load(guild_id).await.access;
+
+    let builder = CreateMessage::new()
+        .embed(wizard_embed(&choice))
+        .components(wizard_components(&choice));
+    let mut wizard = match msg.channel_id.send_message(&ctx.http, builder).await {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::error!("Failed to start setup: {:?}", e);
+            return;
+        }
+    };
+
+    loop {
+        let interaction = match wizard
+            .await_component_interaction(&ctx.shard)
+            .author_id(msg.author.id)
+            .timeout(WIZARD_TIMEOUT)
+            .await
+        {
+            Some(i) => i,
+            None => {
+                let embed = CreateEmbed::new()
+                    .description("Setup timed out, nothing was changed")
+                    .color(0x6C757D);
+                let _ = wizard
+                    .edit(&ctx.http, EditMessage::new().embed(embed).components(Vec::new()))
+                    .await;
+                return;
+            }
+        };
+
+        match (interaction.data.custom_id.as_str(), &interaction.data.kind) {
+            (CHANNEL_SELECT, ComponentInteractionDataKind::ChannelSelect { values }) => {
+                // The first pick is the main channel, the one members get pointed to
+                choice.channel_id = values.first().copied();
+                choice.extra_channels = values.iter().skip(1).copied().collect();
+            }
+            (ROLE_SELECT, ComponentInteractionDataKind::RoleSelect { values }) => {
+                choice.role_id = values.first().copied();
+            }
+            (RESTRICT_BUTTON, _) => choice.restrict = !choice.restrict,
+            (VOICE_TEXT_BUTTON, _) => choice.voice_text = !choice.voice_text,
+            (SAVE_BUTTON, _) => {
+                let _ = interaction
+                    .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+                    .await;
+
+                let embed = match apply(&ctx, guild_id, &choice).await {
+                    Ok(summary) => CreateEmbed::new().description(summary).color(0x00AAFF),
+                    Err(e) => {
+                        tracing::error!("Setup of {} failed: {:?}", guild_id, e);
+                        CreateEmbed::new()
+                            .description(format!("Setup failed: {} 😞", e))
+                            .color(0xFF0000)
+                    }
+                };
+                let _ = wizard
+                    .edit(&ctx.http, EditMessage::new().embed(embed).components(Vec::new()))
+                    .await;
+                return;
+            }
+            (CANCEL_BUTTON, _) => {
+                let _ = interaction
+                    .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+                    .await;
+                let embed = CreateEmbed::new()
+                    .description("Setup cancelled, nothing was changed")
+                    .color(0x6C757D);
+                let _ = wizard
+                    .edit(&ctx.http, EditMessage::new().embed(embed).components(Vec::new()))
+                    .await;
+                return;
+            }
+            _ => {}
+        }
+
+        let _ = interaction
+            .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+            .await;
+        let _ = wizard
+            .edit(
+                &ctx.http,
+                EditMessage::new()
+                    .embed(wizard_embed(&choice))
+                    .components(wizard_components(&choice)),
+            )
+            .await;
+    }
+}
+
+fn wizard_embed(choice: &AccessSettings) -> CreateEmbed {
+    let channel = match choice.channel_id {
+        Some(id) => std::iter::once(id)
+            .chain(choice.extra_channels.iter().copied())
+            .map(|id| format!("<#{}>", id))
+            .collect::<Vec<_>>()
+            .join(", "),
+        None => format!("create #{}", CHANNEL_NAME),
+    };
+    let voice_text = if choice.voice_text { "on" } else { "off" };
+    let role = match choice.role_id {
+        Some(id) => format!("<@&{}>", id),
+        None => "everyone".to_string(),
+    };
+    let restrict = if choice.restrict { "on" } else { "off, anyone can use me anywhere" };
+
+    CreateEmbed::new()
+        .title("⚙️ Setup")
+        .description(format!(
+            "Pick the channels I take commands in, the first one being the main one, or none \
+             to create #{}, and the role allowed to use me, or none for everyone.\n\n\
+             **Channels:** {}\n**Voice channel chats:** {}\n**Role:** {}\n**Restricted:** {}",
+            CHANNEL_NAME, channel, voice_text, role, restrict
+        ))
+        .color(0x6C757D)
+}
+
+fn wizard_components(choice: &AccessSettings) -> Vec<CreateActionRow> {
+    let channel = CreateSelectMenu::new(
+        CHANNEL_SELECT,
+        CreateSelectMenuKind::Channel {
+            channel_types: Some(vec![ChannelType::Text]),
+            default_channels: choice
+                .channel_id
+                .map(|id| std::iter::once(id).chain(choice.extra_channels.iter().copied()).collect()),
+        },
+    )
+    .placeholder("Command channels")
+    .min_values(0)
+    .max_values(MAX_CHANNELS);
+
+    let role = CreateSelectMenu::new(
+        ROLE_SELECT,
+        CreateSelectMenuKind::Role {
+            default_roles: choice.role_id.map(|id| vec![id]),
+        },
+    )
+    .placeholder("Role allowed to use the bot")
+    .min_values(0)
+    .max_values(1);
+
+    let restrict = CreateButton::new(RESTRICT_BUTTON)
+        .label(if choice.restrict { "Restrict: on" } else { "Restrict: off" })
+        .style(ButtonStyle::Secondary);
+    let voice_text = CreateButton::new(VOICE_TEXT_BUTTON)
+        .label(if choice.voice_text { "Voice chats: on" } else { "Voice chats: off" })
+        .style(ButtonStyle::Secondary);
+    let save = CreateButton::new(SAVE_BUTTON)
+        .label("Save")
+        .style(ButtonStyle::Success);
+    let cancel = CreateButton::new(CANCEL_BUTTON)
+        .label("Cancel")
+        .style(ButtonStyle::Danger);
+
+    vec![
+        CreateActionRow::SelectMenu(channel),
+        CreateActionRow::SelectMenu(role),
+        CreateActionRow::Buttons(vec![restrict, voice_text, save, cancel]),
+    ]
+}
+
+async fn apply(ctx: &Context, guild_id: GuildId, choice: &AccessSettings) -> Result<String, Error> {
+    for id in choice.channel_id.iter().chain(&choice.extra_channels) {
+        let missing = CHANNEL_PERMISSIONS.difference(bot_permissions(ctx, guild_id, Some(*id)).await?);
+        if !missing.is_empty() {
+            return Err(anyhow!("I'm missing {} in <#{}>", missing, id));
+        }
+    }
+
+    let channel_id = match choice.channel_id {
+        Some(id) => id,
+        None => match find_channel(ctx, guild_id, |c| {
+            c.kind == ChannelType::Text && c.name == CHANNEL_NAME
+        })? {
+            Some(id) => id,
+            None => create_channel(ctx, guild_id, choice.role_id.filter(|_| choice.restrict)).await?,
+        },
+    };
+
+    let access = AccessSettings {
+        channel_id: Some(channel_id),
+        ..choice.clone()
+    };
+    settings::update(guild_id, |s| s.access = access).await?;
+
+    let mut places = std::iter::once(channel_id)
+        .chain(choice.extra_channels.iter().copied())
+        .map(|id| format!("<#{}>", id))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if choice.voice_text {
+        places.push_str(" and voice channel chats");
+    }
+
+    let summary = match (choice.restrict, choice.role_id) {
+        (false, _) => "Anyone can use me in any channel".to_string(),
+        (true, Some(role)) => format!("Members with <@&{}> can use me in {}", role, places),
+        (true, None) => format!("Everyone can use me in {}", places),
+    };
+
+    Ok(format!("✅ Setup saved\n{}", summary))
+}
+
+/// Creates `#kumar-channel` under the bot category. With a `role`, only that role may
+/// write there besides the bot.
+async fn create_channel(
+    ctx: &Context,
+    guild_id: GuildId,
+    role: Option<RoleId>,
+) -> Result<ChannelId, Error> {
+    let mut needed = Permissions::MANAGE_CHANNELS;
+    if role.is_some() {
+        needed |= Permissions::MANAGE_ROLES | CHANNEL_PERMISSIONS;
+    }
+    let missing = needed.difference(bot_permissions(ctx, guild_id, None).await?);
+    if !missing.is_empty() {
+        return Err(anyhow!("I'm missing {} to create #{}", missing, CHANNEL_NAME));
+    }
+
+    let category_id = match find_channel(ctx, guild_id, |c| {
+        c.kind == ChannelType::Category && c.name == CATEGORY_NAME
+    })? {
+        Some(id) => id,
+        None => {
+            guild_id
+                .create_channel(
+                    &ctx.http,
+                    CreateChannel::new(CATEGORY_NAME).kind(ChannelType::Category),
+                )
+                .await?
+                .id
+        }
+    };
+
+    let mut builder = CreateChannel::new(CHANNEL_NAME)
+        .kind(ChannelType::Text)
+        .category(category_id);
+
+    if let Some(role) = role {
+        let bot_id = ctx.cache.current_user().id;
+        builder = builder.permissions([
+            PermissionOverwrite {
+                allow: Permissions::empty(),
+                deny: Permissions::SEND_MESSAGES,
+                kind: PermissionOverwriteType::Role(guild_id.everyone_role()),
+            },
+            PermissionOverwrite {
+                allow: Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
+                deny: Permissions::empty(),
+                kind: PermissionOverwriteType::Role(role),
+            },
+            // Keep the bot able to answer in its own channel
+            PermissionOverwrite {
+                allow: CHANNEL_PERMISSIONS,
+                deny: Permissions::empty(),
+                kind: PermissionOverwriteType::Member(bot_id),
+            },
+        ]);
+    }
+
+    Ok(guild_id.create_channel(&ctx.http, builder).await?.id)
+}
+
+/// First cached channel of `guild_id` matching `f`. Errors when the guild is not cached,
+/// so nothing gets created twice just because the cache is still filling.
+fn find_channel(
+    ctx: &Context,
+    guild_id: GuildId,
+    f: impl Fn(&GuildChannel) -> bool,
+) -> Result<Option<ChannelId>, Error> {
+    let guild = ctx
+        .cache
+        .guild(guild_id)
+        .ok_or_else(|| anyhow!("Guild {} is not cached yet", guild_id))?;
+
+    Ok(guild.channels.values().find(|c| f(c)).map(|c| c.id))
+}
+
+/// What the bot may do in `channel`, or server-wide without one.
+async fn bot_permissions(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel: Option<ChannelId>,
+) -> Result<Permissions, Error> {
+    let bot_id = ctx.cache.current_user().id;
+    let member = guild_id.member(ctx, bot_id).await?;
+
+    let guild = ctx
+        .cache
+        .guild(guild_id)
+        .ok_or_else(|| anyhow!("Guild {} is not cached yet", guild_id))?;
+
+    let permissions = match channel.and_then(|id| guild.channels.get(&id)) {
+        Some(channel) => guild.user_permissions_in(channel, &member),
+        #[allow(deprecated)]
+        None => guild.member_permissions(&member),
+    };
+
+    Ok(permissions)
+}
@@ -1,13 +1,19 @@
+use std::sync::Arc;
+
 use serenity::{
-    all::{ActivityData, ActivityType, OnlineStatus, User},
+    all::{ActivityData, ActivityType, ChannelId, GuildId, OnlineStatus, User, UserId},
     client::Context,
     model::prelude::Message,
 };
 use songbird::Event;
 
 use crate::{
-    bot::MusicStateKey,
-    commands::music::{event::OnDisconnect, queue::GuildMusicSession},
+    bot::{HttpKey, MusicStateKey, SourceRegistryKey},
+    commands::music::{
+        event::OnDisconnect,
+        prefetch::Prefetcher,
+        queue::{self, GuildMusicSession},
+    },
     utils::serenity_utils,
 };
 
@@ -22,20 +28,20 @@ pub async fn join(ctx: Context, msg: Message) {
             Err(_) => None,
         };
 
-        let maybe_channel_id = ctx.cache.guild(guild_id).and_then(|guild| {
-            guild
-                .voice_states
-                .get(&user_id)
-                .and_then(|voice| voice.channel_id)
-        });
+        let maybe_channel_id = user_channel(&ctx, guild_id, user_id);
 
         if let Some(channel_id) = maybe_channel_id {
             if let Some(manager) = songbird::get(&ctx).await {
-                let data = ctx.data.read().await;
-                let music_state = data.get::<MusicStateKey>().unwrap();
-                let mut state = music_state.lock().await;
-
-                if state.music_sessions.get(&guild_id).is_none() {
+                let (music_state, sources, http_client) = {
+                    let data = ctx.data.read().await;
+                    (
+                        data.get::<MusicStateKey>().cloned().unwrap(),
+                        data.get::<SourceRegistryKey>().cloned().unwrap(),
+                        data.get::<HttpKey>().cloned().unwrap(),
+                    )
+                };
+
+                if music_state.session(guild_id).is_none() {
                     match manager.join(guild_id, channel_id).await {
                         Ok(join_result) => {
                             ctx.set_presence(Some(ActivityData {
@@ -48,24 +54,32 @@ pub async fn join(ctx: Context, msg: Message) {
                                 state: None,
                             }), OnlineStatus::Online);
 
-                            let sessions =
-                                state.music_sessions.entry(guild_id).or_insert_with(|| {
-                                    GuildMusicSession::new(
-                                        Some(join_result.clone()),
-                                        guild_id.clone(),
-                                        channel_id.clone(),
-                                    )
-                                });
-
-                            if let Some(call) = &sessions.voice_state.call {
-                                let mut call_guard = call.lock().await;
-
-                                let _ = call_guard.add_global_event(
-                                    Event::Core(songbird::CoreEvent::DriverDisconnect),
-                                    OnDisconnect {
-                                        ctx: ctx.clone(),
-                                        msg: msg.clone(),
-                                    },
+                            let session = music_state.insert(GuildMusicSession::new(
+                                Some(join_result.clone()),
+                                guild_id.clone(),
+                                channel_id.clone(),
+                                msg.channel_id,
+                            ));
+
+                            let mut session_guard = session.lock().await;
+                            if session_guard.prefetch.is_none() {
+                                session_guard.prefetch = Some(Prefetcher::spawn(
+                                    Arc::downgrade(&session),
+                                    sources,
+                                    http_client,
+                                ));
+                            }
+                            drop(session_guard);
+
+                            let mut call_guard = join_result.lock().await;
+
+                            for event in [
+                                songbird::CoreEvent::DriverDisconnect,
+                                songbird::CoreEvent::DriverReconnect,
+                            ] {
+                                call_guard.add_global_event(
+                                    Event::Core(event),
+                                    OnDisconnect { ctx: ctx.clone() },
                                 );
                             }
                         }
@@ -88,16 +102,6 @@ pub async fn join(ctx: Context, msg: Message) {
 }
 
 pub async fn leave(ctx: Context, msg: Message) {
-    ctx.set_presence(
-        Some(ActivityData {
-            name: "on Sleeping".to_string(),
-            kind: ActivityType::Playing,
-            state: None,
-            url: None,
-        }),
-        OnlineStatus::Idle,
-    );
-
     let guild_id = match msg.guild_id {
         Some(g) => g,
         None => {
@@ -106,16 +110,105 @@ pub async fn leave(ctx: Context, msg: Message) {
         }
     };
 
+    teardown(&ctx, guild_id).await;
+}
+
+/// Moves the bot into the invoker's voice channel, keeping the queue and current track.
+pub async fn summon(ctx: Context, msg: Message) {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => return,
+    };
+
+    let channel_id = match user_channel(&ctx, guild_id, msg.author.id) {
+        Some(c) => c,
+        None => {
+            let _ = serenity_utils::send_embed(&ctx, &msg, "You're not in voice channel", 0xFF0000).await;
+            return;
+        }
+    };
+
+    let session = match queue::guild_session(&ctx, guild_id).await {
+        Some(s) => s,
+        None => return join(ctx, msg).await,
+    };
+
+    if session.lock().await.channel_id == channel_id {
+        let _ = serenity_utils::send_embed(&ctx, &msg, "I'm already in your channel", 0x6C757D).await;
+        return;
+    }
+
     let manager = match songbird::get(&ctx).await {
         Some(m) => m,
         None => return,
     };
 
-    let data = ctx.data.read().await;
-    let music_state = data.get::<MusicStateKey>().unwrap();
-    let mut state = music_state.lock().await;
+    match manager.join(guild_id, channel_id).await {
+        Ok(_) => {
+            session.lock().await.channel_id = channel_id;
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &format!("🔀 Moved to <#{}>", channel_id),
+                0x00AAFF,
+            )
+            .await;
+        }
+        Err(e) => {
+            tracing::error!("Failed to move to {}: {:?}", channel_id, e);
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                "Failed to connect voice channel 😞",
+                0xFF0000,
+            )
+            .await;
+        }
+    }
+}
+
+fn user_channel(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+    ctx.cache.guild(guild_id).and_then(|guild| {
+        guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|voice| voice.channel_id)
+    })
+}
 
-    let _ = manager.remove(guild_id).await;
+/// The one way out of a voice session: stops every track, detaches the driver events,
+/// resets presence and drops both the session and the `Call`.
+pub async fn teardown(ctx: &Context, guild_id: GuildId) {
+    ctx.set_presence(
+        Some(ActivityData {
+            name: "on Sleeping".to_string(),
+            kind: ActivityType::Playing,
+            state: None,
+            url: None,
+        }),
+        OnlineStatus::Idle,
+    );
+
+    let music_state = ctx.data.read().await.get::<MusicStateKey>().cloned().unwrap();
+
+    if let Some(session) = music_state.remove(guild_id) {
+        let (handle, call) = {
+            let mut guard = session.lock().await;
+            guard.prefetch = None;
+            (guard.interrupt(), guard.voice_state.call.take())
+        };
+
+        if let Some(handle) = handle {
+            let _ = handle.stop();
+        }
+        if let Some(call) = call {
+            let mut call = call.lock().await;
+            call.remove_all_global_events();
+            call.stop();
+        }
+    }
 
-    state.music_sessions.remove(&guild_id);
+    if let Some(manager) = songbird::get(ctx).await {
+        let _ = manager.remove(guild_id).await;
+    }
 }
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use serenity::all::{ChannelId, ChannelType, GuildId, UserId};
+use serenity::prelude::*;
+
+use crate::commands::onboarding;
+use crate::store::settings;
+
+/// Every command the bot answers to, anything else starting with `pb!` is ignored.
+const COMMANDS: &[&str] = &[
+    "ping", "join", "leave", "setup", "playlist", "playnext", "playnow", "play", "pause",
+    "resume", "summon", "move-bot", "stop", "skip", "volume", "history", "previous", "replay",
+    "local", "library", "queue", "autoplay", "like", "favorites", "limits", "lyrics",
+];
+
+/// How long a member is left alone after being pointed to the music channel.
+const REDIRECT_COOLDOWN: Duration = Duration::from_secs(60);
+
+static LAST_REDIRECT: LazyLock<Mutex<HashMap<UserId, Instant>>> = LazyLock::new(Default::default);
+
+/// Name of the command `content` invokes, `None` for anything the bot does not answer to.
+pub fn command_name(content: &str) -> Option<&'static str> {
+    let name = content.strip_prefix("pb!")?.split_whitespace().next()?;
+    COMMANDS.iter().copied().find(|c| *c == name)
+}
+
+/// The music channel a command sent in `channel_id` belongs in, `None` when it may be used
+/// right there.
+pub async fn redirect_target(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Option<ChannelId> {
+    let access = settings::load(guild_id).await.access;
+    if !access.restrict {
+        return None;
+    }
+
+    if access.extra_channels.contains(&channel_id) {
+        return None;
+    }
+
+    let is_voice = ctx.cache.guild(guild_id).is_some_and(|guild| {
+        guild
+            .channels
+            .get(&channel_id)
+            .is_some_and(|c| matches!(c.kind, ChannelType::Voice | ChannelType::Stage))
+    });
+    if access.voice_text && is_voice {
+        return None;
+    }
+
+    onboarding::command_channel(ctx, guild_id, &access).filter(|allowed| *allowed != channel_id)
+}
+
+/// Whether `user_id` may be told about the music channel again, so a member spamming
+/// commands in the wrong place does not get a reply for each.
+pub fn may_redirect(user_id: UserId) -> bool {
+    let mut last = LAST_REDIRECT.lock().unwrap();
+    let now = Instant::now();
+
+    last.retain(|_, at| now.duration_since(*at) < REDIRECT_COOLDOWN);
+    match last.get(&user_id) {
+        Some(_) => false,
+        None => {
+            last.insert(user_id, now);
+            true
+        }
+    }
+}
+
+pub fn redirect_text(channel_id: ChannelId) -> String {
+    format!("🎵 Music commands go in <#{}>, see you there!", channel_id)
+}
@@ -1,9 +1,12 @@
-use serenity::all::{CreateEmbed, CreateMessage};
+use serenity::all::GuildId;
 use serenity::model::channel::Message;
 use serenity::prelude::*;
+use tracing::Instrument;
 
+use super::access;
 use crate::commands;
-use crate::token::registry::TokenRegistry;
+use crate::metrics;
+use crate::store::settings;
 use crate::utils::serenity_utils;
 
 pub async fn handle_message(ctx: Context, msg: Message) {
@@ -11,7 +14,6 @@ pub async fn handle_message(ctx: Context, msg: Message) {
         return;
     }
 
-    let channel_name = r"kumar-channel";
     let guild_id = match msg.guild_id {
         Some(gid) => gid,
         None => {
@@ -19,59 +21,94 @@ pub async fn handle_message(ctx: Context, msg: Message) {
         }
     };
 
-    let registry = TokenRegistry::new();
     let content = msg.content.to_lowercase();
 
-    let result = ctx.http().get_channels(guild_id).await;
-
-    let channels = match result {
-        Ok(channels) => channels,
-        Err(err) => {
-            tracing::error!("Error: {:?}", err);
-            return;
-        }
+    // Anything that is not a command is none of our business
+    let command = match access::command_name(&content) {
+        Some(c) => c,
+        None => return,
     };
 
-    let allowed_channel = match channels.iter().find(|f| f.name == channel_name) {
-        Some(c) => c.clone(),
-        None => {
-            let result = serenity_utils::create_channel_from_id(&ctx, &msg).await;
+    let span = tracing::info_span!(
+        "command",
+        command,
+        guild_id = %guild_id,
+        user_id = %msg.author.id,
+        channel_id = %msg.channel_id,
+    );
+    let timer = metrics::COMMAND_DURATION.with_label_values(&[command]).start_timer();
+    let outcome = run(ctx, msg, guild_id, command).instrument(span).await;
+    timer.observe_duration();
+    metrics::COMMANDS.with_label_values(&[command, outcome]).inc();
+}
+
+/// Handles a command, returns whether it `ran`, was `redirected` or was `forbidden`.
+async fn run(ctx: Context, msg: Message, guild_id: GuildId, command: &str) -> &'static str {
+    tracing::info!("Received {:?}", msg.content);
 
-            match result {
-                Ok(r) => r,
-                Err(err) => {
-                    tracing::error!("Error : {:?}", err);
-                    return;
-                }
-            }
+    // Admins can always reach the wizard, even when the restriction locks them out
+    if command == "setup" {
+        commands::onboarding::setup(ctx.clone(), msg.clone()).await;
+        return "ran";
+    }
+
+    if let Some(allowed_channel) = access::redirect_target(&ctx, guild_id, msg.channel_id).await {
+        if access::may_redirect(msg.author.id) {
+            let _ = serenity_utils::send_embed(
+                &ctx,
+                &msg,
+                &access::redirect_text(allowed_channel),
+                0x6C757D,
+            )
+            .await;
         }
-    };
+        return "redirected";
+    }
 
-    if msg.channel_id != allowed_channel.id {
-        let embed = CreateEmbed::default()
-            .description("Messages must be sent in this channel")
-            .color(0xFF0000);
+    let access = settings::load(guild_id).await.access;
 
-        let builder = CreateMessage::default().embed(embed);
-        let _ = allowed_channel.id.send_message(&ctx.http, builder).await;
-        return
+    if access.restrict
+        && let Some(role) = access.role_id
+        && !msg.member.as_ref().is_some_and(|m| m.roles.contains(&role))
+        && !serenity_utils::can_manage_guild(&ctx, &msg)
+    {
+        let _ = serenity_utils::send_embed(
+            &ctx,
+            &msg,
+            &format!("Only members with <@&{}> can use me", role),
+            0xFF0000,
+        )
+        .await;
+        return "forbidden";
     }
 
-    if content.starts_with("pb!ping") {
-        commands::greeting::run(ctx.clone(), msg.clone()).await;
-    } else if content.starts_with("pb!join") {
-        commands::voice::join(ctx.clone(), msg.clone()).await;
-    } else if content.starts_with("pb!leave") {
-        commands::voice::leave(ctx.clone(), msg.clone()).await;
-    } else if content.starts_with("pb!play") {
-        commands::music::track::run(ctx.clone(), msg.clone(), registry).await;
-    } else if content.starts_with("pb!pause") {
-        commands::music::track::pause(ctx.clone(), msg.clone()).await;
-    } else if content.starts_with("pb!resume") {
-        commands::music::track::resume(ctx.clone(), msg.clone()).await;
-    } else if content.starts_with("pb!skip") {
-        commands::music::track::skip(ctx.clone(), msg.clone()).await;
-    } else if content.starts_with("pb!volume") {
-        commands::music::track::volume(ctx.clone(), msg.clone()).await;
+    match command {
+        "ping" => commands::greeting::run(ctx.clone(), msg.clone()).await,
+        "join" => commands::voice::join(ctx.clone(), msg.clone()).await,
+        "leave" => commands::voice::leave(ctx.clone(), msg.clone()).await,
+        "playlist" => commands::music::playlist::run(ctx.clone(), msg.clone()).await,
+        "playnext" => commands::music::track::play_next(ctx.clone(), msg.clone()).await,
+        "playnow" => commands::music::track::play_now(ctx.clone(), msg.clone()).await,
+        "play" => commands::music::track::run(ctx.clone(), msg.clone()).await,
+        "pause" => commands::music::track::pause(ctx.clone(), msg.clone()).await,
+        "resume" => commands::music::track::resume(ctx.clone(), msg.clone()).await,
+        "summon" | "move-bot" => commands::voice::summon(ctx.clone(), msg.clone()).await,
+        "stop" => commands::music::track::stop(ctx.clone(), msg.clone()).await,
+        "skip" => commands::music::track::skip(ctx.clone(), msg.clone()).await,
+        "volume" => commands::music::track::volume(ctx.clone(), msg.clone()).await,
+        "history" => commands::music::history::history(ctx.clone(), msg.clone()).await,
+        "previous" => commands::music::history::previous(ctx.clone(), msg.clone()).await,
+        "replay" => commands::music::history::replay(ctx.clone(), msg.clone()).await,
+        "local" => commands::music::library::local(ctx.clone(), msg.clone()).await,
+        "library" => commands::music::library::library(ctx.clone(), msg.clone()).await,
+        "queue" => commands::music::track::queue(ctx.clone(), msg.clone()).await,
+        "autoplay" => commands::music::autoplay::toggle(ctx.clone(), msg.clone()).await,
+        "like" => commands::music::favorites::like(ctx.clone(), msg.clone()).await,
+        "favorites" => commands::music::favorites::list(ctx.clone(), msg.clone()).await,
+        "limits" => commands::music::limits::run(ctx.clone(), msg.clone()).await,
+        "lyrics" => commands::music::lyrics::run(ctx.clone(), msg.clone()).await,
+        _ => {}
     }
+
+    "ran"
 }
@@ -1,14 +1,17 @@
+mod access;
 mod message;
 
 use message::handle_message;
 use serenity::all::{
-    ActivityData, ActivityType, Command, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage, Guild, Interaction, OnlineStatus
+    ActivityData, ActivityType, Command, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage, Guild, GuildChannel, Interaction, OnlineStatus, VoiceState
 };
 use serenity::async_trait;
 use serenity::model::{channel::Message, gateway::Ready};
 use serenity::prelude::*;
 
-use crate::utils::serenity_utils;
+use crate::commands::music::{favorites, queue};
+use crate::commands::{onboarding, voice};
+use crate::server;
 
 pub struct Handler;
 
@@ -19,15 +22,28 @@ impl EventHandler for Handler {
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            if command.data.name == "ready" {
-                let _ = command.create_response(
-                    &ctx.http,
-                    CreateInteractionResponse::Message(
-                        CreateInteractionResponseMessage::new().content("Bot is ready"),
-                    ),
-                ).await;
+        match interaction {
+            Interaction::Command(command) if command.data.name == "ready" => {
+                let redirect = match command.guild_id {
+                    Some(guild_id) => access::redirect_target(&ctx, guild_id, command.channel_id).await,
+                    None => None,
+                };
+
+                // Only the invoker sees the redirect, so it needs no rate limit
+                let response = match redirect {
+                    Some(channel_id) => CreateInteractionResponseMessage::new()
+                        .content(access::redirect_text(channel_id))
+                        .ephemeral(true),
+                    None => CreateInteractionResponseMessage::new().content("Bot is ready"),
+                };
+                let _ = command
+                    .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+                    .await;
+            }
+            Interaction::Component(component) if component.data.custom_id == favorites::LIKE_BUTTON => {
+                favorites::on_like_button(ctx, component).await;
             }
+            _ => {}
         }
     }
 
@@ -43,14 +59,57 @@ impl EventHandler for Handler {
         );
 
         tracing::info!("{} is online!", ready.user.name);
+        server::mark_ready();
 
         let builder = CreateCommand::new("ready").description("Check if bot is ready");
         let _ = Command::create_global_command(&ctx.http, builder).await;
     }
 
+    async fn voice_state_update(&self, ctx: Context, _old: Option<VoiceState>, new: VoiceState) {
+        if new.user_id != ctx.cache.current_user().id {
+            return;
+        }
+        let Some(guild_id) = new.guild_id else {
+            return;
+        };
+        let Some(session) = queue::guild_session(&ctx, guild_id).await else {
+            return;
+        };
+
+        match new.channel_id {
+            // Dragged to another channel, rejoins have to go there from now on
+            Some(channel_id) => {
+                let mut guard = session.lock().await;
+                if guard.channel_id != channel_id {
+                    tracing::info!("Moved to channel {} in guild {}", channel_id, guild_id);
+                    guard.channel_id = channel_id;
+                }
+            }
+            None => {
+                tracing::info!("Disconnected from voice in guild {}", guild_id);
+                voice::teardown(&ctx, guild_id).await;
+            }
+        }
+    }
+
+    async fn channel_delete(
+        &self,
+        _ctx: Context,
+        channel: GuildChannel,
+        _messages: Option<Vec<Message>>,
+    ) {
+        onboarding::on_channel_delete(&channel).await;
+    }
+
+    async fn channel_update(&self, _ctx: Context, _old: Option<GuildChannel>, new: GuildChannel) {
+        onboarding::on_channel_update(&new).await;
+    }
+
     async fn guild_create(&self, ctx: Context, guild: Guild, is_new: Option<bool>) {
-        if let Some(true) = is_new {
-            let _ = serenity_utils::create_channel_from_guild(ctx, guild).await;
+        if let Some(true) = is_new
+            && let Err(e) = onboarding::ensure(&ctx, guild.id).await
+        {
+            tracing::error!("Failed to onboard guild {}: {:?}", guild.id, e);
         }
     }
 }
@@ -0,0 +1,305 @@
+pub mod tags;
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::store;
+
+const INDEX_FILE: &str = "library/index.json";
+const ARTWORK_DIR: &str = "library/artwork";
+const AUDIO_EXTENSIONS: [&str; 7] = ["flac", "mp3", "m4a", "mp4", "aac", "ogg", "wav"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    /// Path relative to the library root.
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_ms: Option<u32>,
+    pub artwork: Option<PathBuf>,
+    /// Modification time (unix seconds) and size the tags were read at.
+    pub modified: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LibraryIndex {
+    pub entries: HashMap<PathBuf, LibraryEntry>,
+}
+
+#[derive(Debug, Default)]
+pub struct ScanSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub total: usize,
+}
+
+/// A directory of audio files and the tag index built from it.
+pub struct Library {
+    root: PathBuf,
+    index: RwLock<LibraryIndex>,
+    scanning: Mutex<()>,
+}
+
+impl LibraryEntry {
+    pub fn display_title(&self) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            self.path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+    }
+
+    fn haystack(&self) -> String {
+        [
+            self.title.as_deref(),
+            self.artist.as_deref(),
+            self.album.as_deref(),
+            self.path.to_str(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+    }
+}
+
+impl Library {
+    /// Library rooted at `LIBRARY_DIR`, with the index from the last scan. `None` when unset.
+    pub async fn from_env() -> Option<Self> {
+        let root = PathBuf::from(env::var("LIBRARY_DIR").ok().filter(|d| !d.is_empty())?);
+
+        let index = match store::load_json::<LibraryIndex>(INDEX_FILE).await {
+            Ok(index) => index.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable library index: {:?}", e);
+                LibraryIndex::default()
+            }
+        };
+
+        Some(Self {
+            root,
+            index: RwLock::new(index),
+            scanning: Mutex::new(()),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Walks the library and only re-reads tags of files whose size or mtime changed.
+    pub async fn rescan(&self) -> Result<ScanSummary, Error> {
+        let _scanning = self.scanning.lock().await;
+
+        let previous = self.index.read().await.entries.clone();
+        let root = self.root.clone();
+
+        let (index, summary) = tokio::task::spawn_blocking(move || scan(&root, previous)).await??;
+
+        store::save_json(INDEX_FILE, &index).await?;
+        *self.index.write().await = index;
+
+        tracing::info!(
+            "Library scanned: {} added, {} updated, {} removed, {} total",
+            summary.added,
+            summary.updated,
+            summary.removed,
+            summary.total
+        );
+
+        Ok(summary)
+    }
+
+    pub async fn get(&self, path: &Path) -> Option<LibraryEntry> {
+        self.index.read().await.entries.get(path).cloned()
+    }
+
+    /// Entries matching every word of `query`, best title matches first.
+    pub async fn search(&self, query: &str, limit: usize) -> Vec<LibraryEntry> {
+        let terms: Vec<String> = query.split_whitespace().map(|t| t.to_lowercase()).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let index = self.index.read().await;
+        let mut matches: Vec<(usize, &LibraryEntry)> = index
+            .entries
+            .values()
+            .filter_map(|entry| {
+                let haystack = entry.haystack();
+                if !terms.iter().all(|t| haystack.contains(t)) {
+                    return None;
+                }
+
+                let title = entry.display_title().to_lowercase();
+                let score = terms.iter().filter(|t| title.contains(t.as_str())).count();
+                Some((score, entry))
+            })
+            .collect();
+
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.cmp(a_score).then_with(|| a.path.cmp(&b.path))
+        });
+
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, entry)| entry.clone())
+            .collect()
+    }
+}
+
+fn scan(
+    root: &Path,
+    mut previous: HashMap<PathBuf, LibraryEntry>,
+) -> Result<(LibraryIndex, ScanSummary), Error> {
+    let mut summary = ScanSummary::default();
+    let mut entries = HashMap::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        // Only an unreadable root fails the scan, anything below is logged and skipped
+        let items = match std::fs::read_dir(&dir) {
+            Ok(items) => items,
+            Err(e) if dir != root => {
+                tracing::warn!("Skipping unreadable directory {}: {:?}", dir.display(), e);
+                // What was indexed there stays until the directory can be read again
+                let relative_dir = dir.strip_prefix(root)?;
+                let kept = previous
+                    .keys()
+                    .filter(|p| p.starts_with(relative_dir))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for path in kept {
+                    if let Some(entry) = previous.remove(&path) {
+                        entries.insert(path, entry);
+                    }
+                }
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        for item in items {
+            // Symlinked files are read through, a broken link fails here like any unreadable entry
+            let found = item.and_then(|i| {
+                let path = i.path();
+                let linked = i.file_type()?.is_symlink();
+                let meta = if linked { std::fs::metadata(&path)? } else { i.metadata()? };
+                Ok((path, meta, linked))
+            });
+            let (path, meta, linked) = match found {
+                Ok(found) => found,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable entry in {}: {:?}", dir.display(), e);
+                    continue;
+                }
+            };
+
+
+            if meta.is_dir() {
+                // Linked directories are left alone, they could lead back up the tree
+                if !linked {
+                    pending.push(path);
+                }
+                continue;
+            }
+
+            let is_audio = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+            if !is_audio {
+                continue;
+            }
+
+            let relative = path.strip_prefix(root)?.to_path_buf();
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let size = meta.len();
+
+            let entry = match previous.remove(&relative) {
+                Some(old) if old.modified == modified && old.size == size => old,
+                old => {
+                    if old.is_some() {
+                        summary.updated += 1;
+                    } else {
+                        summary.added += 1;
+                    }
+                    read_entry(&path, relative.clone(), modified, size)
+                }
+            };
+
+            entries.insert(relative, entry);
+        }
+    }
+
+    summary.removed = previous.len();
+    summary.total = entries.len();
+
+    Ok((LibraryIndex { entries }, summary))
+}
+
+fn read_entry(path: &Path, relative: PathBuf, modified: u64, size: u64) -> LibraryEntry {
+    let tags = match tags::read_file_tags(path) {
+        Ok(tags) => tags,
+        Err(e) => {
+            tracing::warn!("Could not read tags of {}: {:?}", path.display(), e);
+            tags::AudioTags::default()
+        }
+    };
+
+    let artwork = tags
+        .artwork
+        .and_then(|(media_type, data)| save_artwork(&relative, &media_type, &data));
+
+    LibraryEntry {
+        path: relative,
+        title: tags.title,
+        artist: tags.artist,
+        album: tags.album,
+        duration_ms: tags.duration_ms,
+        artwork,
+        modified,
+        size,
+    }
+}
+
+fn save_artwork(relative: &Path, media_type: &str, data: &[u8]) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    relative.hash(&mut hasher);
+
+    let ext = match media_type {
+        "image/png" => "png",
+        "image/gif" => "gif",
+        _ => "jpg",
+    };
+
+    let dir = store::data_dir().join(ARTWORK_DIR);
+    let path = dir.join(format!("{:016x}.{}", hasher.finish(), ext));
+
+    match std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, data)) {
+        Ok(()) => Some(path),
+        Err(e) => {
+            tracing::warn!("Could not save artwork of {}: {:?}", relative.display(), e);
+            None
+        }
+    }
+}
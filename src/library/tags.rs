@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey};
+use symphonia::core::probe::Hint;
+
+#[derive(Debug, Default)]
+pub struct AudioTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_ms: Option<u32>,
+    /// Embedded cover art as `(media type, bytes)`.
+    pub artwork: Option<(String, Vec<u8>)>,
+}
+
+impl AudioTags {
+    fn apply(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let value = tag.value.to_string();
+            if value.trim().is_empty() {
+                continue;
+            }
+
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => self.title = Some(value),
+                Some(StandardTagKey::Artist) => self.artist = Some(value),
+                Some(StandardTagKey::AlbumArtist) if self.artist.is_none() => {
+                    self.artist = Some(value)
+                }
+                Some(StandardTagKey::Album) => self.album = Some(value),
+                _ => {}
+            }
+        }
+
+        let cover = revision
+            .visuals()
+            .iter()
+            .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+            .or(revision.visuals().first());
+
+        if let Some(visual) = cover {
+            self.artwork = Some((visual.media_type.clone(), visual.data.to_vec()));
+        }
+    }
+}
+
+/// Reads tags and duration of an audio file on disk.
+pub fn read_file_tags(path: &Path) -> Result<AudioTags, Error> {
+    let file = File::open(path)?;
+    let extension = path.extension().and_then(|e| e.to_str());
+
+    read_tags(Box::new(file), extension)
+}
+
+/// Reads tags and duration from any symphonia media source (files, downloaded buffers).
+pub fn read_tags(source: Box<dyn MediaSource>, extension: Option<&str>) -> Result<AudioTags, Error> {
+    let mss = MediaSourceStream::new(source, Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = extension {
+        hint.with_extension(ext);
+    }
+
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut tags = AudioTags::default();
+
+    // Tags in front of the container (ID3v2) first, container tags take precedence
+    if let Some(metadata) = probed.metadata.get()
+        && let Some(revision) = metadata.current()
+    {
+        tags.apply(revision);
+    }
+
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.apply(revision);
+    }
+
+    tags.duration_ms = probed.format.default_track().and_then(|track| {
+        let params = &track.codec_params;
+        match (params.n_frames, params.time_base, params.sample_rate) {
+            (Some(frames), Some(time_base), _) => {
+                let time = time_base.calc_time(frames);
+                Some((time.seconds * 1000) as u32 + (time.frac * 1000.0) as u32)
+            }
+            (Some(frames), None, Some(rate)) => Some((frames * 1000 / rate as u64) as u32),
+            _ => None,
+        }
+    });
+
+    Ok(tags)
+}
@@ -0,0 +1,68 @@
+use std::env;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+const LOG_FILE_PREFIX: &str = "kumar-bot.log";
+
+/// Sets up logging from the environment. `LOG_FORMAT` is `text` (default) or `json`,
+/// `LOG_DIR` additionally writes logs there, rotated per `LOG_ROTATION` (`daily`,
+/// `hourly` or `never`, default `daily`). Our spans log how long they took once closed.
+///
+/// Keep the returned guard alive, file logs are flushed when it drops.
+pub fn init() -> Option<WorkerGuard> {
+    let json = env::var("LOG_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("json"));
+
+    let mut layers = vec![layer(json, std::io::stdout, true)];
+
+    let guard = match env::var("LOG_DIR").ok().filter(|d| !d.is_empty()) {
+        Some(dir) => {
+            let rotation = match env::var("LOG_ROTATION").unwrap_or_default().to_lowercase().as_str() {
+                "hourly" => Rotation::HOURLY,
+                "never" => Rotation::NEVER,
+                _ => Rotation::DAILY,
+            };
+            let appender = RollingFileAppender::new(rotation, dir, LOG_FILE_PREFIX);
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            layers.push(layer(json, writer, false));
+            Some(guard)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(EnvFilter::from_default_env())
+        .init();
+
+    guard
+}
+
+fn layer<W>(json: bool, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_span_events(FmtSpan::CLOSE);
+
+    // Only our own spans are timed, the ones of serenity and songbird would drown them out
+    let own_spans = filter_fn(|meta| !meta.is_span() || meta.target().starts_with(env!("CARGO_CRATE_NAME")));
+
+    if json {
+        layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_filter(own_spans)
+            .boxed()
+    } else {
+        layer.with_ansi(ansi).with_filter(own_spans).boxed()
+    }
+}
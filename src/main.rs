@@ -1,11 +1,16 @@
 use dotenv::dotenv;
-use tracing_subscriber::EnvFilter;
 
 mod api;
 mod bot;
 mod commands;
 mod handler;
+mod library;
+mod logging;
+mod metrics;
 mod models;
+mod server;
+mod sources;
+mod store;
 mod token;
 mod utils;
 
@@ -13,9 +18,7 @@ mod utils;
 async fn main() {
     dotenv().ok();
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+    let _log_guard = logging::init();
 
     if let Err(e) = bot::start().await {
         tracing::error!("Bot error: {:?}", e);
@@ -0,0 +1,127 @@
+use std::sync::{Arc, LazyLock};
+
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use reqwest::{RequestBuilder, Response};
+use serenity::all::ShardManager;
+
+use crate::commands::music::queue::BotMusicState;
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn register<C: Collector + Clone + 'static>(collector: C) -> C {
+    if let Err(e) = REGISTRY.register(Box::new(collector.clone())) {
+        tracing::error!("Failed to register metric: {:?}", e);
+    }
+    collector
+}
+
+fn counter_vec(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    register(IntCounterVec::new(Opts::new(name, help), labels).unwrap())
+}
+
+fn histogram_vec(name: &str, help: &str, labels: &[&str]) -> HistogramVec {
+    register(HistogramVec::new(HistogramOpts::new(name, help), labels).unwrap())
+}
+
+/// Commands handled, by name and whether they ran, were redirected or were refused.
+pub static COMMANDS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter_vec("kumar_commands_total", "Commands handled", &["command", "outcome"])
+});
+pub static COMMAND_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    histogram_vec("kumar_command_duration_seconds", "Time spent handling a command", &["command"])
+});
+
+pub static TRACKS_PLAYED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register(IntCounter::new("kumar_tracks_played_total", "Tracks started").unwrap())
+});
+/// Tracks that stopped playing, by `finished`, `skipped` or `failed`.
+pub static TRACKS_ENDED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter_vec("kumar_tracks_ended_total", "Tracks that stopped playing", &["reason"])
+});
+/// Whether a track's input was already resolved by the prefetcher when its turn came.
+pub static INPUT_CACHE: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter_vec("kumar_input_cache_total", "Track inputs by prefetch hit or miss", &["result"])
+});
+
+pub static API_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    histogram_vec("kumar_api_request_duration_seconds", "External API latency", &["api", "endpoint"])
+});
+/// Failed external calls, by HTTP status or `network`.
+pub static API_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter_vec("kumar_api_errors_total", "Failed external API calls", &["api", "code"])
+});
+pub static TOKEN_REFRESHES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    counter_vec("kumar_token_refreshes_total", "Spotify token refreshes", &["outcome"])
+});
+
+static VOICE_SESSIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("kumar_voice_sessions", "Active voice sessions").unwrap())
+});
+static QUEUE_LENGTH: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register(
+        IntGaugeVec::new(Opts::new("kumar_queue_length", "Tracks waiting per guild"), &["guild_id"])
+            .unwrap(),
+    )
+});
+static GATEWAY_LATENCY: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register(
+        GaugeVec::new(
+            Opts::new("kumar_gateway_latency_seconds", "Heartbeat latency per shard"),
+            &["shard"],
+        )
+        .unwrap(),
+    )
+});
+
+/// Sends `request`, timing it and counting failures under `api`.
+pub async fn api_call(
+    api: &str,
+    endpoint: &str,
+    request: RequestBuilder,
+) -> reqwest::Result<Response> {
+    let timer = API_LATENCY.with_label_values(&[api, endpoint]).start_timer();
+    let res = request.send().await;
+    timer.observe_duration();
+
+    match &res {
+        Ok(r) if !r.status().is_success() => {
+            API_ERRORS.with_label_values(&[api, r.status().as_str()]).inc()
+        }
+        Err(_) => API_ERRORS.with_label_values(&[api, "network"]).inc(),
+        Ok(_) => {}
+    }
+
+    res
+}
+
+/// Every metric in the Prometheus text format, gauges of the current state refreshed first.
+pub async fn render(music_state: &BotMusicState, shard_manager: &Arc<ShardManager>) -> String {
+    let sessions = music_state.sessions();
+    VOICE_SESSIONS.set(sessions.len() as i64);
+
+    QUEUE_LENGTH.reset();
+    for session in sessions {
+        let guard = session.lock().await;
+        QUEUE_LENGTH
+            .with_label_values(&[&guard.guild_id.to_string()])
+            .set(guard.voice_state.upcoming().len() as i64);
+    }
+
+    for (id, runner) in shard_manager.runners.lock().await.iter() {
+        if let Some(latency) = runner.latency {
+            GATEWAY_LATENCY
+                .with_label_values(&[&id.to_string()])
+                .set(latency.as_secs_f64());
+        }
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        tracing::error!("Failed to encode metrics: {:?}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
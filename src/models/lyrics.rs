@@ -0,0 +1,87 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LrclibRecord {
+    pub plain_lyrics: Option<String>,
+    pub synced_lyrics: Option<String>,
+    #[serde(default)]
+    pub instrumental: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LyricLine {
+    /// Offset from the start of the track, when the provider had timings.
+    pub at_ms: Option<u32>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lyrics {
+    pub lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+    pub fn plain(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| LyricLine {
+                at_ms: None,
+                text: line.trim().to_string(),
+            })
+            .collect();
+
+        Self { lines }
+    }
+
+    /// Parses LRC, e.g. `[01:02.50] line`. Lines without a timestamp are dropped.
+    pub fn from_lrc(text: &str) -> Self {
+        let mut lines = Vec::new();
+
+        for raw in text.lines() {
+            let mut rest = raw.trim();
+            let mut stamps = Vec::new();
+
+            // A line can carry several stamps when it repeats, e.g. a chorus
+            while let Some(inner) = rest.strip_prefix('[') {
+                let Some((stamp, after)) = inner.split_once(']') else {
+                    break;
+                };
+                match parse_stamp(stamp) {
+                    Some(ms) => stamps.push(ms),
+                    None => break,
+                }
+                rest = after;
+            }
+
+            for at_ms in stamps {
+                lines.push(LyricLine {
+                    at_ms: Some(at_ms),
+                    text: rest.trim().to_string(),
+                });
+            }
+        }
+
+        lines.sort_by_key(|line| line.at_ms);
+        Self { lines }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.lines.iter().any(|line| line.at_ms.is_some())
+    }
+
+    /// Index of the line being sung at `position_ms`.
+    pub fn line_at(&self, position_ms: u32) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|line| line.at_ms.is_some_and(|at| at <= position_ms))
+    }
+}
+
+fn parse_stamp(stamp: &str) -> Option<u32> {
+    let (minutes, seconds) = stamp.split_once(':')?;
+    let minutes = minutes.parse::<u32>().ok()?;
+    let seconds = seconds.parse::<f64>().ok()?;
+
+    Some(minutes * 60_000 + (seconds * 1000.0) as u32)
+}
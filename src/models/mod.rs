@@ -1,2 +1,4 @@
+pub mod lyrics;
 pub mod spotify;
-pub mod youtube;
\ No newline at end of file
+pub mod youtube;
+pub mod ytdlp;
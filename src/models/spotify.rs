@@ -58,3 +58,20 @@ pub struct Image {
     pub width: Option<u32>,
     pub height: Option<u32>,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct SpotifyPlaylistTracksResponse {
+    pub items: Vec<SpotifyPlaylistItem>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpotifyPlaylistItem {
+    /// Kept raw: local files and podcast episodes don't fit `SpotifyTrackItem`.
+    pub track: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpotifyRecommendationsResponse {
+    pub tracks: Vec<SpotifyTrackItem>,
+}
@@ -1,5 +1,9 @@
+use std::fmt;
+
 use serde::Deserialize;
 
+use crate::models::ytdlp::YtDlpEntry;
+
 #[derive(Debug, Deserialize)]
 pub struct ApiYoutubeResponse {
     pub items: Vec<ApiItem>,
@@ -18,6 +22,20 @@ pub struct YoutubeError {
     pub status: String //TODO: Should be ENUM
 }
 
+impl YoutubeError {
+    pub fn is_quota_exceeded(&self) -> bool {
+        self.errors.iter().any(|e| e.reason == "quotaExceeded")
+    }
+}
+
+impl fmt::Display for YoutubeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for YoutubeError {}
+
 #[derive(Debug, Deserialize)]
 pub struct ErrorMessage {
     pub message: String,
@@ -50,15 +68,39 @@ pub struct YoutubeSearchResult {
     pub title: String,
     pub artist: String,
     pub video_id: String,
+    pub duration_ms: Option<u32>,
     pub source: &'static str,
 }
 
+impl YoutubeSearchResult {
+    pub fn watch_url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.video_id)
+    }
+
+    pub fn thumbnail_url(&self) -> String {
+        format!("https://i.ytimg.com/vi/{}/hqdefault.jpg", self.video_id)
+    }
+}
+
 impl From<ApiItem> for YoutubeSearchResult {
     fn from(item: ApiItem) -> Self {
         Self {
             title: item.snippet.title,
             artist: item.snippet.channel_title,
             video_id: item.id.video_id,
+            duration_ms: None,
+            source: "youtube",
+        }
+    }
+}
+
+impl From<YtDlpEntry> for YoutubeSearchResult {
+    fn from(entry: YtDlpEntry) -> Self {
+        Self {
+            artist: entry.author().unwrap_or_default().to_string(),
+            duration_ms: entry.duration_ms(),
+            title: entry.title.unwrap_or_default(),
+            video_id: entry.id,
             source: "youtube",
         }
     }
@@ -0,0 +1,24 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct YtDlpEntry {
+    pub id: String,
+    pub title: Option<String>,
+    pub channel: Option<String>,
+    pub uploader: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<f64>,
+    pub webpage_url: Option<String>,
+    pub thumbnail: Option<String>,
+    pub is_live: Option<bool>,
+}
+
+impl YtDlpEntry {
+    pub fn author(&self) -> Option<&str> {
+        self.channel.as_deref().or(self.uploader.as_deref())
+    }
+
+    pub fn duration_ms(&self) -> Option<u32> {
+        self.duration.map(|secs| (secs * 1000.0) as u32)
+    }
+}
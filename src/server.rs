@@ -0,0 +1,285 @@
+use std::convert::Infallible;
+use std::env;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::json;
+use serenity::all::ShardManager;
+use serenity::gateway::ConnectionStage;
+
+use crate::api::{spotify, ytdlp};
+use crate::commands::music::queue::BotMusicState;
+use crate::metrics;
+use crate::token::registry::TokenRegistry;
+
+/// How long the dependency check waits on each dependency before calling it down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often Spotify and yt-dlp are checked, probes only read the last results.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Set once the gateway sent `ready`, the bot is not ready to serve before that.
+static READY: AtomicBool = AtomicBool::new(false);
+
+static DEPENDENCIES: LazyLock<RwLock<Dependencies>> = LazyLock::new(Default::default);
+
+/// Last results of the dependency check.
+#[derive(Debug, Clone, Default)]
+struct Dependencies {
+    /// Whether the check ran at least once.
+    checked: bool,
+    spotify_configured: bool,
+    spotify_token: bool,
+    ytdlp_version: Option<String>,
+}
+
+/// What the status endpoints report on.
+#[derive(Clone)]
+pub struct Status {
+    pub music_state: Arc<BotMusicState>,
+    pub shard_manager: Arc<ShardManager>,
+    pub tokens: TokenRegistry,
+}
+
+/// Flips `/readyz`, called from the `ready` event.
+pub fn mark_ready() {
+    READY.store(true, Ordering::Relaxed);
+}
+
+/// Address of the status server from `HTTP_ADDR` (e.g. `0.0.0.0:9100`), `None` leaves it off.
+pub fn addr_from_env() -> Option<SocketAddr> {
+    let addr = env::var("HTTP_ADDR").ok().filter(|a| !a.is_empty())?;
+    match addr.parse() {
+        Ok(addr) => Some(addr),
+        Err(e) => {
+            tracing::error!("Ignoring invalid HTTP_ADDR {:?}: {:?}", addr, e);
+            None
+        }
+    }
+}
+
+/// Serves `/metrics` in the Prometheus text format, `/healthz` and `/readyz` until the
+/// process exits. Both health endpoints answer 503 while no shard is connected, `/readyz`
+/// also until `ready` fired.
+pub async fn serve(addr: SocketAddr, status: Status) {
+    tokio::spawn(check_dependencies(status.tokens.clone()));
+
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to bind status server to {}: {:?}", addr, e);
+            return;
+        }
+    };
+
+    tracing::info!("Serving status on http://{}", addr);
+    serve_on(listener, status).await;
+}
+
+/// Answers requests on an already bound `listener` until the process exits.
+async fn serve_on(listener: TcpListener, status: Status) {
+    let make_service = make_service_fn(move |_| {
+        let status = status.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, status.clone()))) }
+    });
+
+    let server = match Server::from_tcp(listener) {
+        Ok(builder) => builder.serve(make_service),
+        Err(e) => {
+            tracing::error!("Failed to start status server: {:?}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = server.await {
+        tracing::error!("Status server failed: {:?}", e);
+    }
+}
+
+async fn handle(req: Request<Body>, status: Status) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            let body = metrics::render(&status.music_state, &status.shard_manager).await;
+            Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Body::from(body))
+        }
+        (&Method::GET, "/healthz") => {
+            let (connected, body) = health(&status).await;
+            let code = if connected {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            json_response(code, body)
+        }
+        (&Method::GET, "/readyz") => {
+            let (connected, body) = health(&status).await;
+            let code = if READY.load(Ordering::Relaxed) && connected {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            json_response(code, body)
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Not found")),
+    };
+
+    Ok(response.unwrap_or_default())
+}
+
+/// Health report of the bot and what it depends on, along with whether any shard is
+/// connected to the gateway. Nothing here waits on anything but the shard manager.
+async fn health(status: &Status) -> (bool, serde_json::Value) {
+    let shards = status
+        .shard_manager
+        .runners
+        .lock()
+        .await
+        .iter()
+        .map(|(id, runner)| (id.0, runner.stage, runner.latency))
+        .collect::<Vec<_>>();
+    let connected = shards.iter().any(|(_, stage, _)| *stage == ConnectionStage::Connected);
+
+    let dependencies = DEPENDENCIES.read().unwrap().clone();
+
+    let body = json!({
+        "ready": READY.load(Ordering::Relaxed),
+        "gateway": {
+            "connected": connected,
+            "shards": shards
+                .iter()
+                .map(|(id, stage, latency)| json!({
+                    "id": id,
+                    "stage": stage.to_string(),
+                    "latency_ms": latency.map(|l| l.as_millis() as u64),
+                }))
+                .collect::<Vec<_>>(),
+        },
+        "dependencies_checked": dependencies.checked,
+        "spotify": {
+            "configured": dependencies.spotify_configured,
+            "token": dependencies.spotify_token,
+        },
+        "ytdlp": {
+            "present": dependencies.ytdlp_version.is_some(),
+            "version": dependencies.ytdlp_version,
+        },
+        "sessions": status.music_state.sessions().len(),
+    });
+
+    (connected, body)
+}
+
+/// Checks Spotify and yt-dlp every `CHECK_INTERVAL`, so probes never spawn processes or
+/// request tokens themselves.
+async fn check_dependencies(tokens: TokenRegistry) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // Without credentials there is nothing to check, and trying would only log errors
+        let spotify_configured =
+            env::var("SPOTIFY_CLIENT_ID").is_ok() && env::var("SPOTIFY_SECRET_ID").is_ok();
+        let spotify_token = spotify_configured
+            && tokio::time::timeout(CHECK_TIMEOUT, spotify::get_token(&tokens))
+                .await
+                .is_ok_and(|token| token.is_ok());
+        let ytdlp_version = match tokio::time::timeout(CHECK_TIMEOUT, ytdlp::version()).await {
+            Ok(Ok(version)) => Some(version),
+            _ => None,
+        };
+
+        *DEPENDENCIES.write().unwrap() = Dependencies {
+            checked: true,
+            spotify_configured,
+            spotify_token,
+            ytdlp_version,
+        };
+    }
+}
+
+fn json_response(
+    code: StatusCode,
+    body: serde_json::Value,
+) -> Result<Response<Body>, hyper::http::Error> {
+    Response::builder()
+        .status(code)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use serenity::all::{Cache, GatewayIntents, Http, ShardManagerOptions};
+    use serenity::prelude::TypeMap;
+    use serenity::model::id::{ChannelId, GuildId};
+
+    use super::*;
+    use crate::commands::music::queue::GuildMusicSession;
+
+    fn status() -> Status {
+        let (shard_manager, _) = ShardManager::new(ShardManagerOptions {
+            data: Arc::new(tokio::sync::RwLock::new(TypeMap::new())),
+            event_handlers: Vec::new(),
+            raw_event_handlers: Vec::new(),
+            framework: Arc::new(OnceLock::new()),
+            shard_index: 0,
+            shard_init: 0,
+            shard_total: 1,
+            voice_manager: None,
+            ws_url: Arc::new(tokio::sync::Mutex::new(String::new())),
+            cache: Arc::new(Cache::new()),
+            http: Arc::new(Http::new("")),
+            intents: GatewayIntents::empty(),
+            presence: None,
+        });
+
+        let music_state = Arc::new(BotMusicState::new());
+        music_state.insert(GuildMusicSession::new(
+            None,
+            GuildId::new(1),
+            ChannelId::new(1),
+            ChannelId::new(1),
+        ));
+
+        Status {
+            music_state,
+            shard_manager,
+            tokens: TokenRegistry::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_expose_the_registered_families() {
+        // Labelled families only show up once they have a child
+        metrics::TRACKS_ENDED.with_label_values(&["finished"]).inc();
+        metrics::API_LATENCY.with_label_values(&["test", "metrics"]).observe(0.1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_on(listener, status()));
+
+        let res = reqwest::get(format!("http://{}/metrics", addr)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = res.text().await.unwrap();
+        for family in [
+            "kumar_tracks_ended_total",
+            "kumar_queue_length",
+            "kumar_api_request_duration_seconds",
+        ] {
+            assert!(body.contains(&format!("# TYPE {} ", family)), "{} missing:\n{}", family, body);
+        }
+        assert!(body.contains("kumar_queue_length{guild_id=\"1\"} 0"));
+    }
+}
@@ -0,0 +1,124 @@
+use std::env;
+use std::io::Cursor;
+
+use anyhow::{Error, Result};
+use reqwest::{Client, Url};
+use serenity::all::Attachment;
+use serenity::async_trait;
+use songbird::input::{HttpRequest, Input};
+
+use crate::library::tags;
+use crate::sources::{MediaSource, TrackInfo};
+
+const HOSTS: [&str; 2] = ["cdn.discordapp.com/attachments/", "media.discordapp.net/attachments/"];
+
+/// Audio files uploaded to Discord, metadata probed from the file itself.
+pub struct AttachmentSource {
+    client: Client,
+}
+
+/// Largest upload the bot will download and play, `ATTACHMENT_MAX_MB` (default 25).
+pub fn max_attachment_bytes() -> u64 {
+    env::var("ATTACHMENT_MAX_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(25)
+        * 1024
+        * 1024
+}
+
+/// Why an attachment can't be queued, `None` when it is fine.
+pub fn validate(attachment: &Attachment) -> Option<String> {
+    let content_type = attachment.content_type.as_deref().unwrap_or_default();
+
+    if !(content_type.starts_with("audio/") || content_type == "application/ogg") {
+        return Some(format!("{} is not an audio file", attachment.filename));
+    }
+
+    if attachment.size as u64 > max_attachment_bytes() {
+        return Some(format!(
+            "{} is larger than {} MB",
+            attachment.filename,
+            max_attachment_bytes() / 1024 / 1024
+        ));
+    }
+
+    None
+}
+
+impl AttachmentSource {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+        }
+    }
+}
+
+fn file_name(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()?
+        .path_segments()?
+        .next_back()
+        .map(str::to_string)
+}
+
+#[async_trait]
+impl MediaSource for AttachmentSource {
+    fn name(&self) -> &'static str {
+        "attachment"
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let without_scheme = query.split("://").nth(1).unwrap_or(query);
+        HOSTS.iter().any(|host| without_scheme.starts_with(host))
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error> {
+        let mut res = self.client.get(query).send().await?.error_for_status()?;
+        let limit = max_attachment_bytes();
+
+        if res.content_length().is_some_and(|len| len > limit) {
+            return Err(anyhow::anyhow!("Attachment is too large"));
+        }
+
+        // The length header is optional, so the limit is enforced on what actually arrives
+        let mut bytes = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            if (bytes.len() + chunk.len()) as u64 > limit {
+                return Err(anyhow::anyhow!("Attachment is too large"));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let name = file_name(query).unwrap_or_else(|| "attachment".to_string());
+        let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_string());
+
+        let probed = tokio::task::spawn_blocking(move || {
+            tags::read_tags(Box::new(Cursor::new(bytes)), extension.as_deref())
+        })
+        .await?;
+
+        let tags = match probed {
+            Ok(tags) => tags,
+            Err(e) => {
+                tracing::warn!("Could not probe attachment {}: {:?}", name, e);
+                return Ok(Vec::new());
+            }
+        };
+
+        Ok(vec![TrackInfo {
+            source: self.name().to_string(),
+            url: query.to_string(),
+            title: tags.title.unwrap_or(name),
+            artist: tags.artist.unwrap_or_else(|| "unknown".to_string()),
+            duration_ms: tags.duration_ms,
+            thumbnail: None,
+            playback_url: None,
+            is_live: false,
+        }])
+    }
+
+    async fn create_input(&self, track: &TrackInfo, client: Client) -> Result<Input, Error> {
+        Ok(Input::from(HttpRequest::new(client, track.url.clone())))
+    }
+}
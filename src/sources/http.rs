@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use reqwest::{Client, Response, Url, header::CONTENT_TYPE};
+use serenity::async_trait;
+use songbird::input::{HttpRequest, Input};
+
+use crate::sources::{MediaSource, TrackInfo, is_url};
+
+/// Direct audio files and Icecast/Shoutcast streams, including `.m3u`/`.pls` playlists.
+/// Registered last so site-specific sources get the first pick of every URL.
+pub struct HttpStreamSource {
+    client: Client,
+}
+
+/// What a single GET against a stream told us.
+struct StreamProbe {
+    content_type: String,
+    is_live: bool,
+    station: Option<String>,
+    icy_metaint: Option<usize>,
+}
+
+const PLAYLIST_TYPES: [&str; 4] = [
+    "audio/x-mpegurl",
+    "audio/mpegurl",
+    "audio/x-scpls",
+    "application/pls+xml",
+];
+
+const PLAYLIST_EXTENSIONS: [&str; 2] = [".m3u", ".pls"];
+
+impl HttpStreamSource {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+        }
+    }
+
+    async fn open(&self, url: &str) -> Result<Response, Error> {
+        Ok(self
+            .client
+            .get(url)
+            .header("Icy-MetaData", "1")
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?)
+    }
+
+    async fn resolve_stream(&self, url: &str) -> Result<Option<TrackInfo>, Error> {
+        let res = self.open(url).await?;
+        Ok(self.track_from_response(url, res).await)
+    }
+
+    async fn track_from_response(&self, url: &str, res: Response) -> Option<TrackInfo> {
+        let probe = StreamProbe::from_response(&res);
+
+        if !probe.is_audio() {
+            tracing::warn!("{} is not an audio stream ({})", url, probe.content_type);
+            return None;
+        }
+
+        let now_playing = match probe.icy_metaint {
+            Some(metaint) => read_icy_title(res, metaint).await,
+            None => None,
+        };
+
+        let station = probe.station.unwrap_or_else(|| file_name(url));
+
+        Some(TrackInfo {
+            source: self.name().to_string(),
+            url: url.to_string(),
+            title: now_playing.unwrap_or_else(|| station.clone()),
+            artist: station,
+            duration_ms: None,
+            thumbnail: None,
+            playback_url: None,
+            is_live: probe.is_live,
+        })
+    }
+
+    async fn resolve_playlist(&self, url: &str, body: &str) -> Result<Vec<TrackInfo>, Error> {
+        let base = Url::parse(url)?;
+        let mut tracks = Vec::new();
+
+        for entry in parse_playlist(body) {
+            let entry_url = match base.join(&entry) {
+                Ok(u) => u.to_string(),
+                Err(_) => continue,
+            };
+
+            match self.resolve_stream(&entry_url).await {
+                // Radio playlists list mirrors of the same stream, one is enough
+                Ok(Some(track)) if track.is_live => return Ok(vec![track]),
+                Ok(Some(track)) => tracks.push(track),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Skipping playlist entry {}: {:?}", entry_url, e),
+            }
+        }
+
+        Ok(tracks)
+    }
+}
+
+impl StreamProbe {
+    fn from_response(res: &Response) -> Self {
+        let headers = res.headers();
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let content_type = header(CONTENT_TYPE.as_str())
+            .map(|ct| ct.split(';').next().unwrap_or_default().to_lowercase())
+            .unwrap_or_default();
+        let icy_metaint = header("icy-metaint").and_then(|v| v.parse().ok());
+        let station = header("icy-name");
+        let is_live = icy_metaint.is_some()
+            || station.is_some()
+            || header("icy-br").is_some()
+            || res.content_length().is_none();
+
+        Self {
+            content_type,
+            is_live,
+            station,
+            icy_metaint,
+        }
+    }
+
+    fn is_audio(&self) -> bool {
+        self.content_type.starts_with("audio/")
+            || self.content_type == "application/ogg"
+            || (self.content_type.is_empty() && self.icy_metaint.is_some())
+    }
+}
+
+/// What an ICY stream is playing right now, read from a fresh connection to it.
+pub async fn stream_title(client: &Client, url: &str) -> Option<String> {
+    let res = client
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    let metaint = StreamProbe::from_response(&res).icy_metaint?;
+
+    read_icy_title(res, metaint).await
+}
+
+/// Reads the first ICY metadata block and pulls `StreamTitle` out of it.
+async fn read_icy_title(mut res: Response, metaint: usize) -> Option<String> {
+    let read = async {
+        let mut buf: Vec<u8> = Vec::new();
+
+        while buf.len() <= metaint {
+            buf.extend_from_slice(&res.chunk().await.ok()??);
+        }
+
+        let meta_len = buf[metaint] as usize * 16;
+        let meta_start = metaint + 1;
+
+        while buf.len() < meta_start + meta_len {
+            buf.extend_from_slice(&res.chunk().await.ok()??);
+        }
+
+        let meta = String::from_utf8_lossy(&buf[meta_start..meta_start + meta_len]).to_string();
+        parse_stream_title(&meta)
+    };
+
+    tokio::time::timeout(Duration::from_secs(5), read).await.ok()?
+}
+
+fn parse_stream_title(meta: &str) -> Option<String> {
+    let start = meta.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &meta[start..];
+    let end = rest.find("';").unwrap_or(rest.len());
+
+    Some(rest[..end].trim().to_string()).filter(|t| !t.is_empty())
+}
+
+/// Stream URLs listed in an `.m3u` or `.pls` body.
+fn parse_playlist(body: &str) -> Vec<String> {
+    let lines = body.lines().map(str::trim).filter(|l| !l.is_empty());
+
+    if body.trim_start().starts_with("[playlist]") {
+        lines
+            .filter(|l| l.to_lowercase().starts_with("file"))
+            .filter_map(|l| l.split_once('=').map(|(_, url)| url.trim().to_string()))
+            .collect()
+    } else {
+        lines
+            .filter(|l| !l.starts_with('#'))
+            .map(|l| l.to_string())
+            .collect()
+    }
+}
+
+fn file_name(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            u.path_segments()
+                .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+                .or(u.host_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+fn is_playlist_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    PLAYLIST_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+#[async_trait]
+impl MediaSource for HttpStreamSource {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        is_url(query)
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error> {
+        if is_playlist_url(query) {
+            let body = self.open(query).await?.text().await?;
+            return self.resolve_playlist(query, &body).await;
+        }
+
+        let res = self.open(query).await?;
+        let probe = StreamProbe::from_response(&res);
+
+        if PLAYLIST_TYPES.contains(&probe.content_type.as_str()) {
+            let body = res.text().await?;
+            return self.resolve_playlist(query, &body).await;
+        }
+
+        Ok(self.track_from_response(query, res).await.into_iter().collect())
+    }
+
+    async fn create_input(&self, track: &TrackInfo, client: Client) -> Result<Input, Error> {
+        Ok(Input::from(HttpRequest::new(client, track.url.clone())))
+    }
+}
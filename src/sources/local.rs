@@ -0,0 +1,80 @@
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+use anyhow::{Error, Result};
+use reqwest::Client;
+use serenity::async_trait;
+use songbird::input::{File, Input};
+
+use crate::library::{Library, LibraryEntry};
+use crate::sources::{MediaSource, TrackInfo};
+
+const PREFIX: &str = "local:";
+const URL_PREFIX: &str = "local://";
+
+/// Files from the local music library, addressed as `local://<path>` or searched with `local:<query>`.
+pub struct LocalSource {
+    library: Arc<Library>,
+}
+
+impl LocalSource {
+    pub fn new(library: Arc<Library>) -> Self {
+        Self { library }
+    }
+
+    fn track_info(&self, entry: LibraryEntry) -> TrackInfo {
+        TrackInfo {
+            source: self.name().to_string(),
+            url: format!("{}{}", URL_PREFIX, entry.path.to_string_lossy()),
+            title: entry.display_title(),
+            artist: entry.artist.unwrap_or_else(|| "unknown".to_string()),
+            duration_ms: entry.duration_ms,
+            thumbnail: entry.artwork.map(|p| format!("file://{}", p.display())),
+            playback_url: None,
+            is_live: false,
+        }
+    }
+}
+
+/// Only plain relative paths may address library files.
+fn is_safe_relative(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[async_trait]
+impl MediaSource for LocalSource {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        query.starts_with(PREFIX)
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error> {
+        if let Some(path) = query.strip_prefix(URL_PREFIX)
+            && let Some(entry) = self.library.get(Path::new(path)).await
+        {
+            return Ok(vec![self.track_info(entry)]);
+        }
+
+        let terms = query.strip_prefix(PREFIX).unwrap_or(query).trim();
+        Ok(self
+            .library
+            .search(terms, 1)
+            .await
+            .into_iter()
+            .map(|entry| self.track_info(entry))
+            .collect())
+    }
+
+    async fn create_input(&self, track: &TrackInfo, _client: Client) -> Result<Input, Error> {
+        let relative = Path::new(track.url.strip_prefix(URL_PREFIX).unwrap_or_default());
+
+        if !is_safe_relative(relative) || self.library.get(relative).await.is_none() {
+            return Err(anyhow::anyhow!("{} is not in the library", track.url));
+        }
+
+        Ok(Input::from(File::new(self.library.root().join(relative))))
+    }
+}
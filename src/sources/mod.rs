@@ -0,0 +1,134 @@
+pub mod attachment;
+pub mod http;
+pub mod local;
+pub mod spotify;
+pub mod youtube;
+pub mod ytdlp;
+
+use std::sync::Arc;
+
+use anyhow::{Error, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use songbird::input::Input;
+
+use crate::library::Library;
+use crate::token::registry::TokenRegistry;
+
+/// Everything the queue needs to know about a track, independent of where it is played from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackInfo {
+    /// Name of the [`MediaSource`] that resolved this track.
+    pub source: String,
+    pub url: String,
+    pub title: String,
+    pub artist: String,
+    pub duration_ms: Option<u32>,
+    pub thumbnail: Option<String>,
+    /// Where the audio actually comes from when it differs from `url` (e.g. Spotify -> YouTube).
+    pub playback_url: Option<String>,
+    /// Radio and live streams: no duration, no seeking, no preloading.
+    #[serde(default)]
+    pub is_live: bool,
+}
+
+impl TrackInfo {
+    /// Whether the track can be kept in playlists, favorites or history. Discord attachment
+    /// links expire after about a day, so uploads are only ever played right away.
+    pub fn can_persist(&self) -> bool {
+        self.source != "attachment"
+    }
+}
+
+#[async_trait]
+pub trait MediaSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether this source knows how to handle the given `pb!play` argument.
+    fn matches(&self, query: &str) -> bool;
+
+    /// Looks up the metadata of every track behind `query`.
+    async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error>;
+
+    /// Produces the songbird input that plays `track`.
+    async fn create_input(&self, track: &TrackInfo, client: Client) -> Result<Input, Error>;
+
+    /// Tracks similar to `track`, used by autoplay. Sources without recommendations return none.
+    async fn related(&self, _track: &TrackInfo, _limit: usize) -> Result<Vec<TrackInfo>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+pub struct SourceRegistry {
+    sources: Vec<Arc<dyn MediaSource>>,
+}
+
+impl SourceRegistry {
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+        }
+    }
+
+    /// Registry with every built-in source. Order matters: the first source that matches wins.
+    pub fn with_defaults(tokens: TokenRegistry, library: Option<Arc<Library>>) -> Self {
+        let mut registry = Self::new();
+        registry.register(spotify::SpotifySource::new(tokens));
+        if let Some(library) = library {
+            registry.register(local::LocalSource::new(library));
+        }
+        registry.register(ytdlp::YtDlpSource::soundcloud());
+        registry.register(ytdlp::YtDlpSource::bandcamp());
+        registry.register(youtube::YoutubeSource);
+        registry.register(attachment::AttachmentSource::new());
+        registry.register(http::HttpStreamSource::new());
+        registry
+    }
+
+    pub fn register(&mut self, source: impl MediaSource + 'static) {
+        self.sources.push(Arc::new(source));
+    }
+
+    pub fn find(&self, query: &str) -> Option<Arc<dyn MediaSource>> {
+        self.sources.iter().find(|s| s.matches(query)).cloned()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn MediaSource>> {
+        self.sources.iter().find(|s| s.name() == name).cloned()
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error> {
+        match self.find(query) {
+            Some(source) => {
+                tracing::debug!("Resolving {:?} with {} source", query, source.name());
+                source.resolve(query).await
+            }
+            None => {
+                tracing::warn!("Unsupported URL or command: {}", query);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(source = %track.source, url = %track.url))]
+    pub async fn create_input(&self, track: &TrackInfo, client: Client) -> Result<Input, Error> {
+        let source = self
+            .get(&track.source)
+            .ok_or_else(|| anyhow::anyhow!("Unknown media source {}", track.source))?;
+
+        source.create_input(track, client).await
+    }
+
+    pub async fn related(&self, track: &TrackInfo, limit: usize) -> Result<Vec<TrackInfo>, Error> {
+        match self.get(&track.source) {
+            Some(source) => source.related(track, limit).await,
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+pub fn is_url(query: &str) -> bool {
+    query.starts_with("http://") || query.starts_with("https://")
+}
@@ -0,0 +1,138 @@
+use anyhow::{Error, Result};
+use reqwest::Client;
+use serenity::async_trait;
+use songbird::input::Input;
+
+use crate::api::{
+    spotify::{get_playlist_tracks, get_recommendations, get_track_by_id},
+    youtube::search_youtube,
+};
+use crate::models::spotify::SpotifyTrackItem;
+use crate::sources::{
+    MediaSource, TrackInfo,
+    youtube::{related_to_video, ytdl_input},
+};
+use crate::token::registry::TokenRegistry;
+
+/// Spotify track and playlist links. Metadata comes from Spotify, audio from the best YouTube match.
+pub struct SpotifySource {
+    tokens: TokenRegistry,
+}
+
+impl SpotifySource {
+    pub fn new(tokens: TokenRegistry) -> Self {
+        Self { tokens }
+    }
+}
+
+/// Queue entry for a Spotify track, `playback_url` is filled in once a YouTube match is known.
+pub fn track_info(track: SpotifyTrackItem, playback_url: Option<String>) -> TrackInfo {
+    TrackInfo {
+        source: "spotify".to_string(),
+        url: track.external_urls.spotify,
+        artist: track
+            .artists
+            .first()
+            .map(|a| a.name.to_owned())
+            .unwrap_or("unknown".to_owned()),
+        thumbnail: track.album.images.first().map(|a| a.url.clone()),
+        title: track.name,
+        duration_ms: Some(track.duration_ms),
+        playback_url,
+        is_live: false,
+    }
+}
+
+/// Finds the YouTube video that plays a Spotify track.
+async fn find_playback_url(title: &str, artist: &str) -> Result<Option<String>, Error> {
+    let query = format!("{} {}", title, artist);
+    let results = search_youtube(&query).await?;
+
+    Ok(results.into_iter().next().map(|res| res.watch_url()))
+}
+
+#[async_trait]
+impl MediaSource for SpotifySource {
+    fn name(&self) -> &'static str {
+        "spotify"
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        query.contains("open.spotify.com/track/") || query.contains("open.spotify.com/playlist/")
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error> {
+        // Playlist tracks are matched to YouTube only once they are about to play
+        if let Some(playlist_id) = extract_id(query, "/playlist/") {
+            let tracks = get_playlist_tracks(playlist_id, self.tokens.clone()).await?;
+            return Ok(tracks
+                .into_iter()
+                .map(|t| track_info(t, None))
+                .collect());
+        }
+
+        let track_id = match extract_track_id(query) {
+            Some(id) => id,
+            None => {
+                tracing::warn!("Could not extract Spotify ID");
+                return Ok(Vec::new());
+            }
+        };
+
+        let track = get_track_by_id(track_id, self.tokens.clone()).await?;
+        let artist = track
+            .artists
+            .first()
+            .map(|a| a.name.clone())
+            .unwrap_or_default();
+        let playback_url = find_playback_url(&track.name, &artist).await?;
+
+        Ok(vec![track_info(track, playback_url)])
+    }
+
+    async fn create_input(&self, track: &TrackInfo, client: Client) -> Result<Input, Error> {
+        let url = match &track.playback_url {
+            Some(url) => url.clone(),
+            None => find_playback_url(&track.title, &track.artist)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("No YouTube match for {}", track.title))?,
+        };
+
+        Ok(ytdl_input(client, url))
+    }
+
+    async fn related(&self, track: &TrackInfo, limit: usize) -> Result<Vec<TrackInfo>, Error> {
+        let recommendations = match extract_track_id(&track.url) {
+            Some(id) => get_recommendations(&[id], limit as u32, self.tokens.clone()).await,
+            None => Ok(Vec::new()),
+        };
+
+        match recommendations {
+            Ok(tracks) if !tracks.is_empty() => {
+                Ok(tracks.into_iter().map(|t| track_info(t, None)).collect())
+            }
+            result => {
+                if let Err(e) = result {
+                    tracing::warn!("Spotify recommendations failed: {:?}", e);
+                }
+
+                // Fall back to what YouTube plays next to the matched video
+                match &track.playback_url {
+                    Some(url) => related_to_video(url, limit).await,
+                    None => Ok(Vec::new()),
+                }
+            }
+        }
+    }
+}
+
+pub fn extract_track_id(url: &str) -> Option<String> {
+    extract_id(url, "/track/")
+}
+
+fn extract_id(url: &str, kind: &str) -> Option<String> {
+    url.split(kind)
+        .nth(1)
+        .and_then(|s| s.split('?').next())
+        .map(|s| s.to_string())
+}
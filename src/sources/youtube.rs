@@ -0,0 +1,104 @@
+use anyhow::{Error, Result};
+use reqwest::Client;
+use serenity::async_trait;
+use songbird::input::{Input, YoutubeDl};
+
+use crate::api::{
+    youtube::{self, search_youtube},
+    ytdlp,
+};
+use crate::models::youtube::YoutubeSearchResult;
+use crate::sources::{MediaSource, TrackInfo, is_url};
+
+/// YouTube links, and the fallback for plain-text searches.
+pub struct YoutubeSource;
+
+const HOSTS: [&str; 4] = ["youtube.com/", "www.youtube.com/", "music.youtube.com/", "youtu.be/"];
+
+/// Builds a yt-dlp backed input, shared by every source that streams through yt-dlp.
+pub fn ytdl_input(client: Client, url: String) -> Input {
+    Input::from(
+        YoutubeDl::new_ytdl_like(ytdlp::ytdlp_path(), client, url).user_args(vec![
+            "--no-playlist".into(),
+            "-f".into(),
+            "bestaudio[acodec=opus]/bestaudio".into(),
+        ]),
+    )
+}
+
+/// Queue entry for a YouTube search hit.
+pub fn search_result_info(res: YoutubeSearchResult) -> TrackInfo {
+    TrackInfo {
+        source: "youtube".to_string(),
+        url: res.watch_url(),
+        thumbnail: Some(res.thumbnail_url()),
+        title: res.title,
+        artist: res.artist,
+        duration_ms: res.duration_ms,
+        playback_url: None,
+        is_live: false,
+    }
+}
+
+/// Tracks related to the YouTube video at `url`.
+pub async fn related_to_video(url: &str, limit: usize) -> Result<Vec<TrackInfo>, Error> {
+    let video_id = match youtube::video_id(url) {
+        Some(id) => id,
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(youtube::related_videos(&video_id)
+        .await?
+        .into_iter()
+        .take(limit)
+        .map(search_result_info)
+        .collect())
+}
+
+#[async_trait]
+impl MediaSource for YoutubeSource {
+    fn name(&self) -> &'static str {
+        "youtube"
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        if !is_url(query) {
+            return !query.is_empty();
+        }
+
+        let without_scheme = query.split("://").nth(1).unwrap_or(query);
+        HOSTS.iter().any(|host| without_scheme.starts_with(host))
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error> {
+        if !is_url(query) {
+            let result = search_youtube(query).await?.into_iter().next();
+
+            return Ok(result.map(search_result_info).into_iter().collect());
+        }
+
+        let entries = ytdlp::dump_json(query, &["--no-playlist", "--no-warnings"]).await?;
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| TrackInfo {
+                source: self.name().to_string(),
+                url: entry.webpage_url.clone().unwrap_or_else(|| query.to_string()),
+                title: entry.title.clone().unwrap_or_else(|| "unknown".to_string()),
+                artist: entry.author().unwrap_or("unknown").to_string(),
+                duration_ms: entry.duration_ms(),
+                is_live: entry.is_live.unwrap_or(false),
+                thumbnail: entry.thumbnail,
+                playback_url: None,
+            })
+            .collect())
+    }
+
+    async fn create_input(&self, track: &TrackInfo, client: Client) -> Result<Input, Error> {
+        Ok(ytdl_input(client, track.url.clone()))
+    }
+
+    async fn related(&self, track: &TrackInfo, limit: usize) -> Result<Vec<TrackInfo>, Error> {
+        related_to_video(&track.url, limit).await
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{Error, Result};
+use reqwest::Client;
+use serenity::async_trait;
+use songbird::input::Input;
+
+use crate::api::ytdlp;
+use crate::sources::{MediaSource, TrackInfo, is_url, youtube::ytdl_input};
+
+/// Sites whose tracks and sets are resolved entirely through yt-dlp metadata extraction.
+pub struct YtDlpSource {
+    name: &'static str,
+    hosts: &'static [&'static str],
+}
+
+impl YtDlpSource {
+    /// SoundCloud tracks (`soundcloud.com/<user>/<track>`) and sets (`.../sets/<set>`).
+    pub fn soundcloud() -> Self {
+        Self {
+            name: "soundcloud",
+            hosts: &["soundcloud.com", "m.soundcloud.com", "on.soundcloud.com"],
+        }
+    }
+
+    /// Bandcamp tracks (`<artist>.bandcamp.com/track/<x>`) and albums (`.../album/<x>`).
+    pub fn bandcamp() -> Self {
+        Self {
+            name: "bandcamp",
+            hosts: &["bandcamp.com"],
+        }
+    }
+
+    fn host_matches(&self, host: &str) -> bool {
+        self.hosts
+            .iter()
+            .any(|h| host == *h || host.ends_with(&format!(".{}", h)))
+    }
+}
+
+fn host_of(url: &str) -> Option<&str> {
+    url.split("://")
+        .nth(1)
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .map(|host| host.split(':').next().unwrap_or(host))
+}
+
+#[async_trait]
+impl MediaSource for YtDlpSource {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        is_url(query) && host_of(query).is_some_and(|host| self.host_matches(host))
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Vec<TrackInfo>, Error> {
+        let entries = ytdlp::dump_json(query, &["--yes-playlist", "--no-warnings"]).await?;
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| TrackInfo {
+                source: self.name.to_string(),
+                url: entry.webpage_url.clone().unwrap_or_else(|| query.to_string()),
+                title: entry.title.clone().unwrap_or_else(|| "unknown".to_string()),
+                artist: entry
+                    .artist
+                    .as_deref()
+                    .or(entry.author())
+                    .unwrap_or("unknown")
+                    .to_string(),
+                duration_ms: entry.duration_ms(),
+                is_live: entry.is_live.unwrap_or(false),
+                thumbnail: entry.thumbnail,
+                playback_url: None,
+            })
+            .collect())
+    }
+
+    async fn create_input(&self, track: &TrackInfo, client: Client) -> Result<Input, Error> {
+        Ok(ytdl_input(client, track.url.clone()))
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, UserId};
+use tokio::sync::Mutex;
+
+use crate::sources::TrackInfo;
+use crate::store;
+
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Favorite {
+    pub track: TrackInfo,
+    pub liked_by: UserId,
+    pub liked_at: u64,
+}
+
+fn file(guild_id: GuildId) -> PathBuf {
+    PathBuf::from(format!("favorites/{}.json", guild_id))
+}
+
+/// Likes of everyone in a guild, oldest first.
+pub async fn load(guild_id: GuildId) -> Result<Vec<Favorite>, Error> {
+    Ok(store::load_json(file(guild_id)).await?.unwrap_or_default())
+}
+
+/// Likes of one user in a guild, oldest first.
+pub async fn load_user(guild_id: GuildId, user_id: UserId) -> Result<Vec<Favorite>, Error> {
+    let mut favorites = load(guild_id).await?;
+    favorites.retain(|f| f.liked_by == user_id);
+    Ok(favorites)
+}
+
+/// Every track liked in a guild, once each even when several people liked it.
+pub async fn load_guild_tracks(guild_id: GuildId) -> Result<Vec<TrackInfo>, Error> {
+    let mut tracks: Vec<TrackInfo> = Vec::new();
+
+    for favorite in load(guild_id).await? {
+        if !tracks.iter().any(|t| t.url == favorite.track.url) {
+            tracks.push(favorite.track);
+        }
+    }
+
+    Ok(tracks)
+}
+
+/// Loads the likes of `guild_id`, applies `f` and writes them back.
+pub async fn update<R>(
+    guild_id: GuildId,
+    f: impl FnOnce(&mut Vec<Favorite>) -> R,
+) -> Result<R, Error> {
+    let _guard = WRITE_LOCK.lock().await;
+
+    let mut favorites = load(guild_id).await?;
+    let result = f(&mut favorites);
+    store::save_json(file(guild_id), &favorites).await?;
+
+    Ok(result)
+}
+
+/// Adds `track` to the likes of `user_id`, `false` if it was already there.
+pub async fn like(guild_id: GuildId, user_id: UserId, track: TrackInfo) -> Result<bool, Error> {
+    update(guild_id, |favorites| {
+        if favorites
+            .iter()
+            .any(|f| f.liked_by == user_id && f.track.url == track.url)
+        {
+            return false;
+        }
+
+        favorites.push(Favorite {
+            track,
+            liked_by: user_id,
+            liked_at: store::unix_now(),
+        });
+        true
+    })
+    .await
+}
+
+/// Likes `track`, or unlikes it when `user_id` already did. `true` when it is now liked.
+pub async fn toggle(guild_id: GuildId, user_id: UserId, track: TrackInfo) -> Result<bool, Error> {
+    update(guild_id, |favorites| {
+        let before = favorites.len();
+        favorites.retain(|f| !(f.liked_by == user_id && f.track.url == track.url));
+
+        if favorites.len() < before {
+            return false;
+        }
+
+        favorites.push(Favorite {
+            track,
+            liked_by: user_id,
+            liked_at: store::unix_now(),
+        });
+        true
+    })
+    .await
+}
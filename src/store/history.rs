@@ -0,0 +1,50 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, UserId};
+use tokio::sync::Mutex;
+
+use crate::sources::TrackInfo;
+use crate::store;
+
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub track: TrackInfo,
+    pub requested_by: UserId,
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub skipped: bool,
+}
+
+/// How many plays are kept per guild, `HISTORY_LIMIT` (default 500).
+fn limit() -> usize {
+    env::var("HISTORY_LIMIT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(500)
+}
+
+fn file(guild_id: GuildId) -> PathBuf {
+    PathBuf::from(format!("history/{}.json", guild_id))
+}
+
+/// Plays of a guild, oldest first.
+pub async fn load(guild_id: GuildId) -> Result<Vec<HistoryEntry>, Error> {
+    Ok(store::load_json(file(guild_id)).await?.unwrap_or_default())
+}
+
+pub async fn record(guild_id: GuildId, entry: HistoryEntry) -> Result<(), Error> {
+    let _guard = WRITE_LOCK.lock().await;
+
+    let mut entries = load(guild_id).await?;
+    entries.push(entry);
+
+    let overflow = entries.len().saturating_sub(limit());
+    entries.drain(..overflow);
+
+    store::save_json(file(guild_id), &entries).await
+}
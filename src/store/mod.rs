@@ -0,0 +1,51 @@
+pub mod favorites;
+pub mod history;
+pub mod playlist;
+pub mod settings;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Error, Result};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Directory the bot keeps its local state in, `DATA_DIR` or `./data`.
+pub fn data_dir() -> PathBuf {
+    env::var("DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("data"))
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Reads a JSON document under the data directory, `None` if it was never written.
+pub async fn load_json<T: DeserializeOwned>(relative: impl AsRef<Path>) -> Result<Option<T>, Error> {
+    let path = data_dir().join(relative);
+
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes a JSON document under the data directory, replacing it atomically.
+pub async fn save_json<T: Serialize>(relative: impl AsRef<Path>, value: &T) -> Result<(), Error> {
+    let path = data_dir().join(relative);
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec_pretty(value)?).await?;
+    tokio::fs::rename(&tmp, &path).await?;
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, UserId};
+use tokio::sync::Mutex;
+
+use crate::sources::TrackInfo;
+use crate::store;
+
+/// Serializes read-modify-write cycles on playlist files.
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Copy)]
+pub enum PlaylistScope {
+    /// Follows the user across every server.
+    User(UserId),
+    /// Shared by everyone in the server.
+    Guild(GuildId),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    pub created_by: UserId,
+    pub tracks: Vec<TrackInfo>,
+}
+
+/// Playlists of one scope, keyed by lowercased name.
+pub type Playlists = BTreeMap<String, Playlist>;
+
+impl PlaylistScope {
+    fn file(&self) -> PathBuf {
+        match self {
+            Self::User(id) => PathBuf::from(format!("playlists/users/{}.json", id)),
+            Self::Guild(id) => PathBuf::from(format!("playlists/guilds/{}.json", id)),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::User(_) => "personal",
+            Self::Guild(_) => "server",
+        }
+    }
+}
+
+pub fn key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+pub async fn load(scope: PlaylistScope) -> Result<Playlists, Error> {
+    Ok(store::load_json(scope.file()).await?.unwrap_or_default())
+}
+
+/// Loads the playlists of `scope`, applies `f` and writes them back.
+pub async fn update<R>(
+    scope: PlaylistScope,
+    f: impl FnOnce(&mut Playlists) -> R,
+) -> Result<R, Error> {
+    let _guard = WRITE_LOCK.lock().await;
+
+    let mut playlists = load(scope).await?;
+    let result = f(&mut playlists);
+    store::save_json(scope.file(), &playlists).await?;
+
+    Ok(result)
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{LazyLock, RwLock};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, RoleId};
+use tokio::sync::Mutex;
+
+use crate::store;
+
+static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+/// Every write goes through `update`, which keeps this in step with the files.
+static CACHE: LazyLock<RwLock<HashMap<GuildId, GuildSettings>>> = LazyLock::new(Default::default);
+
+/// Per-guild preferences, every field defaults so older files keep loading.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Queue related tracks once the queue runs out.
+    pub autoplay: bool,
+    pub limits: QueueLimits,
+    pub access: AccessSettings,
+}
+
+/// What members may queue, `None` means unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueLimits {
+    /// Tracks waiting to be played.
+    pub max_queue_length: Option<usize>,
+    /// Tracks waiting to be played per member.
+    pub max_tracks_per_user: Option<usize>,
+    /// Longest track accepted, in seconds.
+    pub max_track_duration: Option<u32>,
+    /// Refuse tracks that are already waiting in the queue.
+    pub no_duplicates: bool,
+    /// Interleave tracks by requester instead of strict append order.
+    pub fair_queue: bool,
+}
+
+/// Where and by whom the bot may be used, picked with `pb!setup`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessSettings {
+    /// Main text channel commands are taken from, `None` until onboarding recorded one.
+    pub channel_id: Option<ChannelId>,
+    /// Further text channels commands are taken from.
+    pub extra_channels: Vec<ChannelId>,
+    /// Also take commands in the text chat built into voice channels.
+    pub voice_text: bool,
+    /// Role allowed to use the bot, everyone when `None`.
+    pub role_id: Option<RoleId>,
+    /// Only take commands in the channels above, from members with `role_id`.
+    pub restrict: bool,
+}
+
+impl Default for AccessSettings {
+    fn default() -> Self {
+        Self {
+            channel_id: None,
+            extra_channels: Vec::new(),
+            voice_text: false,
+            role_id: None,
+            restrict: true,
+        }
+    }
+}
+
+fn file(guild_id: GuildId) -> PathBuf {
+    PathBuf::from(format!("settings/{}.json", guild_id))
+}
+
+/// Settings of `guild_id`, read from disk once and served from memory afterwards.
+pub async fn load(guild_id: GuildId) -> GuildSettings {
+    if let Some(settings) = CACHE.read().unwrap().get(&guild_id) {
+        return settings.clone();
+    }
+
+    let settings = match store::load_json(file(guild_id)).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Ignoring unreadable settings of {}: {:?}", guild_id, e);
+            GuildSettings::default()
+        }
+    };
+
+    // An update that landed meanwhile is newer than what was just read
+    CACHE
+        .write()
+        .unwrap()
+        .entry(guild_id)
+        .or_insert(settings)
+        .clone()
+}
+
+/// Loads the settings of `guild_id`, applies `f` and writes them back.
+pub async fn update<R>(
+    guild_id: GuildId,
+    f: impl FnOnce(&mut GuildSettings) -> R,
+) -> Result<R, Error> {
+    let _guard = WRITE_LOCK.lock().await;
+
+    let mut settings = load(guild_id).await;
+    let result = f(&mut settings);
+    store::save_json(file(guild_id), &settings).await?;
+    CACHE.write().unwrap().insert(guild_id, settings);
+
+    Ok(result)
+}
@@ -4,6 +4,8 @@ use reqwest::Client;
 use serde::Deserialize;
 use std::env;
 
+use crate::metrics;
+
 #[derive(Debug, Clone)]
 pub struct SpotifyToken {
     pub access_token: String,
@@ -32,7 +34,10 @@ impl SpotifyTokenManager {
                 Ok(token.access_token.clone())
             }
             _ => {
-                let new_token = Self::fetch_new_token().await?;
+                let new_token = Self::fetch_new_token().await;
+                let outcome = if new_token.is_ok() { "ok" } else { "error" };
+                metrics::TOKEN_REFRESHES.with_label_values(&[outcome]).inc();
+                let new_token = new_token?;
                 let access_token = new_token.access_token.clone();
                 self.token = Some(new_token);
                 Ok(access_token)
@@ -40,6 +45,7 @@ impl SpotifyTokenManager {
         }
     }
 
+    #[tracing::instrument(name = "spotify_token")]
     async fn fetch_new_token() -> Result<SpotifyToken, Box<dyn std::error::Error>> {
         let client_id = env::var("SPOTIFY_CLIENT_ID")?;
         let secret_id = env::var("SPOTIFY_SECRET_ID")?;
@@ -47,12 +53,14 @@ impl SpotifyTokenManager {
         let encoded = general_purpose::STANDARD.encode(credentials);
 
         let client = Client::new();
-        let res = client
-            .post("https://accounts.spotify.com/api/token")
+        let request = client
+            .post(env::var("SPOTIFY_ACCOUNTS_URL").unwrap_or_else(|_| {
+                "https://accounts.spotify.com/api/token".to_string()
+            }))
             .header("Authorization", format!("Basic {}", encoded))
             .header("Content-Type", "application/x-www-form-urlencoded")
-            .body("grant_type=client_credentials")
-            .send()
+            .body("grant_type=client_credentials");
+        let res = metrics::api_call("spotify", "token", request)
             .await?
             .error_for_status()?
             .json::<SpotifyTokenResponse>()
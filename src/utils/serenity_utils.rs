@@ -1,36 +1,45 @@
 use serenity::{
-    all::{
-        ChannelType, CreateChannel, CreateEmbedFooter, EditRole, Guild, GuildChannel, ImageHash,
-        PermissionOverwrite, PermissionOverwriteType, Permissions, User, UserId,
-    },
+    all::{ButtonStyle, ChannelId, CreateAttachment, CreateButton, CreateEmbedFooter, ReactionType},
     builder::{CreateEmbed, CreateMessage},
     client::Context,
     model::prelude::Message,
 };
 
+use crate::commands::music::favorites;
+use crate::sources::TrackInfo;
+
 pub async fn send_embed(
     ctx: &Context,
     msg: &Message,
     description: &str,
     color: u32,
+) -> serenity::Result<Message> {
+    send_channel_embed(ctx, msg.channel_id, description, color).await
+}
+
+/// Posts an embed that answers no message in particular, like playback announcements.
+pub async fn send_channel_embed(
+    ctx: &Context,
+    channel_id: ChannelId,
+    description: &str,
+    color: u32,
 ) -> serenity::Result<Message> {
     let embed = CreateEmbed::default().description(description).color(color);
 
     let builder = CreateMessage::default().embed(embed);
-    msg.channel_id.send_message(&ctx.http, builder).await
+    channel_id.send_message(&ctx.http, builder).await
 }
 
 pub async fn send_track_embed(
     ctx: &Context,
     msg: &Message,
-    title: &str,
-    artist: &str,
-    duration: &u32,
-    thumbnail: &str,
-    cur_index: &str,
-    index: &str,
-    user: User,
+    track: &TrackInfo,
+    heading: &str,
+    cur_index: usize,
+    index: usize,
+    eta_ms: Option<u64>,
 ) -> serenity::Result<Message> {
+    let user = &msg.author;
     let url_picture = match user.avatar {
         Some(hash) => {
             let hash_str = hash.to_string();
@@ -41,212 +50,80 @@ pub async fn send_track_embed(
             )
         }
         None => {
-            format!("https://cdn-icons-png.flaticon.com/512/747/747545.png")
+            "https://cdn-icons-png.flaticon.com/512/747/747545.png".to_string()
         }
     };
 
-    let duration_str = {
-        let total_seconds = duration / 1000;
-        let hours = total_seconds / 3600;
-        let minutes = (total_seconds % 3600) / 60;
-        let seconds = total_seconds % 60;
+    let duration_str = match (track.is_live, track.duration_ms) {
+        (true, _) => "🔴 LIVE".to_string(),
+        (false, Some(duration)) => format_duration(duration),
+        (false, None) => "--:--".to_string(),
+    };
 
-        if hours > 0 {
-            format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
-        } else {
-            format!("{:02}:{:02}", minutes, seconds)
-        }
+    let eta_str = match eta_ms {
+        Some(0) => "Now".to_string(),
+        Some(ms) => format_duration(ms.min(u32::MAX as u64) as u32),
+        None => "Unknown".to_string(),
     };
 
-    let embed = CreateEmbed::new()
-        .title("Added Track Queue")
-        .thumbnail(thumbnail)
+    let mut embed = CreateEmbed::new()
+        .title(heading)
         .fields([
-            ("Track     ", title, true),
-            ("Artist    ", artist, true),
+            ("Track     ", track.title.as_str(), true),
+            ("Artist    ", track.artist.as_str(), true),
             ("Track Length  ", &duration_str, true),
-            ("Current position", cur_index, true),
-            ("Position in queue", index, true),
+            ("Current position", &cur_index.to_string(), true),
+            ("Position in queue", &index.to_string(), true),
+            ("Plays in", &eta_str, true),
         ])
         .footer(CreateEmbedFooter::new(format!("Requested by {}", user.name)).icon_url(url_picture))
         .color(0x00AAFF);
 
-    let builder = CreateMessage::default().embed(embed);
-    msg.channel_id.send_message(&ctx.http, builder).await
-}
-
-pub async fn create_channel_from_guild(
-    ctx: Context,
-    guild: Guild,
-) -> serenity::Result<GuildChannel> {
-    let category_name = r"Bot Channels";
-    let channel_name = r"kumar-channel";
-    let allowed_role_name = r"Kumar"; // Just this role can be use this bot
+    let mut builder = CreateMessage::default();
 
-    let allowed_role = match guild.role_by_name(&allowed_role_name) {
-        Some(r) => r.to_owned(),
-        None => {
-            let role = guild
-                .id
-                .create_role(
-                    &ctx.http,
-                    EditRole::new()
-                        .name(allowed_role_name)
-                        .colour((241, 197, 102))
-                        .permissions(Permissions::SEND_MESSAGES)
-                        .mentionable(true),
-                )
-                .await?;
-
-            role
+    // Local artwork has no URL, upload it alongside the embed instead
+    match track.thumbnail.as_deref().map(|t| (t, t.strip_prefix("file://"))) {
+        Some((_, Some(path))) => {
+            if let Ok(file) = CreateAttachment::path(path).await {
+                embed = embed.thumbnail(format!("attachment://{}", file.filename));
+                builder = builder.add_file(file);
+            }
         }
-    };
-
-    let channels = guild.id.channels(&ctx.http).await?;
+        Some((url, None)) => embed = embed.thumbnail(url),
+        None => {}
+    }
 
-    let category = channels
-        .values()
-        .find(|f| f.kind == ChannelType::Category && f.name == category_name);
+    builder = builder.embed(embed);
 
-    let category_id = if let Some(cat) = category {
-        cat.id
-    } else {
-        let new_cat = guild
-            .id
-            .create_channel(
-                &ctx.http,
-                CreateChannel::new(category_name).kind(ChannelType::Category),
-            )
-            .await?;
+    // Uploads can't be liked, their links expire
+    if !track.can_persist() {
+        return msg.channel_id.send_message(&ctx.http, builder).await;
+    }
 
-        new_cat.id
-    };
-
-    let channel_exists = channels.values().any(|c| {
-        c.kind == ChannelType::Text && c.name == channel_name && c.parent_id == Some(category_id)
-    });
-
-    let channel = if !channel_exists {
-        guild
-            .id
-            .create_channel(
-                &ctx.http,
-                CreateChannel::new(channel_name)
-                    .kind(ChannelType::Text)
-                    .category(category_id)
-                    .permissions([
-                        // Deny from everyone
-                        PermissionOverwrite {
-                            allow: Permissions::empty(),
-                            deny: Permissions::SEND_MESSAGES,
-                            kind: PermissionOverwriteType::Role(guild.id.everyone_role()),
-                        },
-                        // Allow for target role
-                        PermissionOverwrite {
-                            allow: Permissions::all(),
-                            deny: Permissions::empty(),
-                            kind: PermissionOverwriteType::Role(allowed_role.id),
-                        },
-                    ]),
-            )
-            .await?
-    } else {
-        guild
-            .channels(&ctx.http)
-            .await?
-            .values()
-            .find(|c| c.name == channel_name)
-            .cloned()
-            .ok_or_else(|| serenity::Error::Other("Channel not found"))?
-    };
+    let like = CreateButton::new(favorites::LIKE_BUTTON)
+        .emoji(ReactionType::Unicode("❤️".to_string()))
+        .style(ButtonStyle::Secondary);
 
-    Ok(channel)
+    let card = msg.channel_id.send_message(&ctx.http, builder.button(like)).await?;
+    favorites::remember_card(card.id, track.clone());
+    Ok(card)
 }
 
-pub async fn create_channel_from_id(
-    ctx: &Context,
-    msg: &Message,
-) -> serenity::Result<GuildChannel> {
-    let category_name = r"Bot Channels";
-    let channel_name = r"kumar-channel";
-    let allowed_role_name = r"Kumar"; // Just this role can be use this bot
-
-    let guild_id = msg.guild_id.ok_or(serenity::Error::Model(
-        serenity::all::ModelError::GuildNotFound,
-    ))?;
-
-    let roles = guild_id.roles(&ctx.http).await?;
-
-    let allowed_role = match roles.values().find(|v| v.name == allowed_role_name) {
-        Some(r) => r.to_owned(),
-        None => {
-            let role = guild_id
-                .create_role(
-                    &ctx.http,
-                    EditRole::new()
-                        .name(allowed_role_name)
-                        .colour((241, 197, 102))
-                        .permissions(Permissions::SEND_MESSAGES)
-                        .mentionable(true),
-                )
-                .await?;
-            role
-        }
-    };
-
-    let channels = guild_id.channels(&ctx.http).await?;
-
-    let category = channels
-        .values()
-        .find(|f| f.kind == ChannelType::Category && f.name == category_name);
-
-    let category_id = if let Some(cat) = category {
-        cat.id
-    } else {
-        let new_cat = guild_id
-            .create_channel(
-                &ctx.http,
-                CreateChannel::new(category_name).kind(ChannelType::Category),
-            )
-            .await?;
+/// Whether the author of `msg` has Manage Server, as far as the cache knows.
+pub fn can_manage_guild(ctx: &Context, msg: &Message) -> bool {
+    msg.author_permissions(&ctx.cache)
+        .is_some_and(|p| p.manage_guild())
+}
 
-        new_cat.id
-    };
+pub fn format_duration(duration_ms: u32) -> String {
+    let total_seconds = duration_ms / 1000;
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let seconds = total_seconds % 60;
 
-    let channel_exists = channels.values().any(|c| {
-        c.kind == ChannelType::Text && c.name == channel_name && c.parent_id == Some(category_id)
-    });
-
-    let channel = if !channel_exists {
-        guild_id
-            .create_channel(
-                &ctx.http,
-                CreateChannel::new(channel_name)
-                    .kind(ChannelType::Text)
-                    .category(category_id)
-                    .permissions([
-                        // Deny from everyone
-                        PermissionOverwrite {
-                            allow: Permissions::empty(),
-                            deny: Permissions::SEND_MESSAGES,
-                            kind: PermissionOverwriteType::Role(guild_id.everyone_role()),
-                        },
-                        // Allow for target role
-                        PermissionOverwrite {
-                            allow: Permissions::all(),
-                            deny: Permissions::empty(),
-                            kind: PermissionOverwriteType::Role(allowed_role.id),
-                        },
-                    ]),
-            )
-            .await?
+    if hours > 0 {
+        format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
     } else {
-        channels
-            .values()
-            .find(|c| c.name == channel_name)
-            .cloned()
-            .ok_or_else(|| serenity::Error::Other("Channel not found"))?
-    };
-
-    Ok(channel)
+        format!("{:02}:{:02}", minutes, seconds)
+    }
 }